@@ -3,7 +3,7 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::Path;
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct EncryptionKey {
     #[serde(rename = "publicKey")]
     pub public_key: String,
@@ -11,10 +11,35 @@ pub struct EncryptionKey {
     pub secret_key: String,
 }
 
+/// An encrypted file that is tracked in the manifest, keyed by the path of the raw file.
+/// Paths are relative to the directory of the scoob configuration file.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ManifestEntry {
+    pub encrypted: String,
+    pub key: String,
+}
+
+/// How a secret is passed to commands started with `scoob start`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct EnvEntry {
+    /// The name of the environment variable that the secret is passed as.
+    #[serde(rename = "as")]
+    pub name: String,
+}
+
 #[derive(Default, Serialize, Deserialize, Clone, Debug)]
 pub struct Config {
     pub configuration: HashMap<String, String>,
     pub keys: HashMap<String, EncryptionKey>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub manifest: HashMap<String, ManifestEntry>,
+    /// Encrypted file contents, keyed by the name of the environment variable that `start`
+    /// sets to the path of the decrypted file.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub files: HashMap<String, String>,
+    /// Environment variable names for secrets, keyed by the name of the secret.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub env: HashMap<String, EnvEntry>,
 }
 
 impl Config {
@@ -28,14 +53,53 @@ impl Config {
         Config {
             configuration: placeholder_configuration,
             keys: self.keys.clone(),
+            manifest: self.manifest.clone(),
+            files: self.files.clone(),
+            env: self.env.clone(),
+        }
+    }
+
+    /// A configuration without any values or keys, unlike the example configuration returned
+    /// by `Config::default`.
+    pub fn empty() -> Config {
+        Config {
+            configuration: HashMap::new(),
+            keys: HashMap::new(),
+            manifest: HashMap::new(),
+            files: HashMap::new(),
+            env: HashMap::new(),
         }
     }
 
+    /// Merges the changes made in two copies of a configuration, which are compared against the
+    /// configuration they both started from. Values must be compared as ciphertext, which works
+    /// because unchanged values keep their previous ciphertext.
+    pub fn merge(base: &Config, ours: &Config, theirs: &Config) -> Result<Config, &'static str> {
+        Ok(Config {
+            configuration: merge_map(
+                &base.configuration,
+                &ours.configuration,
+                &theirs.configuration,
+            )?,
+            keys: merge_map(&base.keys, &ours.keys, &theirs.keys)?,
+            manifest: merge_map(&base.manifest, &ours.manifest, &theirs.manifest)?,
+            files: merge_map(&base.files, &ours.files, &theirs.files)?,
+            env: merge_map(&base.env, &ours.env, &theirs.env)?,
+        })
+    }
+
     pub fn exists(path: &Path) -> bool {
         let result = std::fs::read_to_string(path);
         result.is_ok()
     }
 
+    pub fn from_yaml(contents: &str) -> Result<Config, &'static str> {
+        match serde_yaml::from_str(contents) {
+            Ok(config) => Ok(config),
+            Err(_) => Err("The scoob configuration file is not valid YAML."),
+        }
+    }
+
     pub fn get(path: &Path) -> Config {
         let result = std::fs::read_to_string(path);
         match result {
@@ -69,8 +133,38 @@ impl Config {
         Config {
             configuration: default_config,
             keys: default_keys,
+            manifest: HashMap::new(),
+            files: HashMap::new(),
+            env: HashMap::new(),
+        }
+    }
+}
+
+fn merge_map<V: Clone + PartialEq>(
+    base: &HashMap<String, V>,
+    ours: &HashMap<String, V>,
+    theirs: &HashMap<String, V>,
+) -> Result<HashMap<String, V>, &'static str> {
+    let mut merged: HashMap<String, V> = HashMap::new();
+
+    let keys = base.keys().chain(ours.keys()).chain(theirs.keys());
+    for key in keys {
+        let (base_value, our_value, their_value) = (base.get(key), ours.get(key), theirs.get(key));
+
+        let value = if our_value == base_value {
+            their_value
+        } else if their_value == base_value || their_value == our_value {
+            our_value
+        } else {
+            return Err("Your changes conflict with changes made to the same keys in the secrets file, so they can't be merged.");
+        };
+
+        if let Some(value) = value {
+            merged.insert(key.to_string(), value.clone());
         }
     }
+
+    Ok(merged)
 }
 
 #[cfg(test)]
@@ -100,10 +194,10 @@ mod tests {
         let mut path = env::current_dir()?;
         path.push("test");
         path.push("secrets.yml");
-        assert_eq!(Config::exists(&path), true);
+        assert!(Config::exists(&path));
         path.pop();
         path.push("does-not-exist.yml");
-        assert_eq!(Config::exists(&path), false);
+        assert!(!Config::exists(&path));
         Ok(())
     }
 
@@ -120,6 +214,30 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_merge() {
+        let base = Config::default();
+        let mut ours = base.clone();
+        let mut theirs = base.clone();
+
+        ours.configuration
+            .insert("OURS".to_string(), "our value".to_string());
+        theirs.configuration.remove("EXAMPLE_KEY");
+        theirs
+            .configuration
+            .insert("THEIRS".to_string(), "their value".to_string());
+
+        let merged = Config::merge(&base, &ours, &theirs).expect("Should merge changes.");
+        assert_eq!(merged.configuration.len(), 2);
+        assert_eq!(merged.configuration["OURS"], "our value");
+        assert_eq!(merged.configuration["THEIRS"], "their value");
+        assert_eq!(merged.keys.len(), 1);
+
+        ours.configuration
+            .insert("THEIRS".to_string(), "a different value".to_string());
+        assert!(Config::merge(&base, &ours, &theirs).is_err());
+    }
+
     #[test]
     fn test_config_placeholders() {
         let mut config = Config::default();
@@ -0,0 +1,368 @@
+use crate::config::Config;
+use crate::encryption::Encryption;
+use colored::Colorize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+pub struct Diff {
+    /// Print the decrypted values of the keys that changed
+    #[structopt(short, long)]
+    show_values: bool,
+    /// Compare the file against its contents at a git revision, instead of a second file
+    #[structopt(short, long)]
+    rev: Option<String>,
+    /// Path to the scoob configuration file
+    #[structopt(parse(from_os_str))]
+    file: PathBuf,
+    /// Path to the scoob configuration file to compare against
+    #[structopt(parse(from_os_str))]
+    other: Option<PathBuf>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Change {
+    Added(String),
+    Removed(String),
+    Changed(String),
+    Renamed(String, String),
+}
+
+impl Change {
+    pub fn key(&self) -> &str {
+        match self {
+            Change::Added(key) | Change::Removed(key) | Change::Changed(key) => key,
+            Change::Renamed(_, key) => key,
+        }
+    }
+}
+
+/// Compares two sets of values. Ciphertexts can only be compared when unchanged values kept
+/// their previous ciphertext, as re-encrypting a value produces a different ciphertext every time.
+pub fn diff_configurations(
+    old: &HashMap<String, String>,
+    new: &HashMap<String, String>,
+) -> Vec<Change> {
+    let mut changes: Vec<Change> = Vec::new();
+
+    for (key, value) in new.iter() {
+        match old.get(key) {
+            None => changes.push(Change::Added(key.to_string())),
+            Some(old_value) if old_value != value => changes.push(Change::Changed(key.to_string())),
+            _ => (),
+        }
+    }
+
+    for key in old.keys() {
+        if !new.contains_key(key) {
+            changes.push(Change::Removed(key.to_string()));
+        }
+    }
+
+    changes.sort_by(|a, b| a.key().cmp(b.key()));
+    changes
+}
+
+/// Collapses a removed key and an added key holding the same value into a rename. Values
+/// that are empty, or that more than one removed or added key holds, such as `true`, are
+/// too common to tell a rename apart from unrelated keys, so they're never matched.
+pub fn detect_renames(
+    changes: Vec<Change>,
+    old: &HashMap<String, String>,
+    new: &HashMap<String, String>,
+) -> Vec<Change> {
+    let mut removed: Vec<String> = changes
+        .iter()
+        .filter_map(|change| match change {
+            Change::Removed(key) => Some(key.to_string()),
+            _ => None,
+        })
+        .collect();
+    let added: Vec<&String> = changes
+        .iter()
+        .filter_map(|change| match change {
+            Change::Added(key) => Some(key),
+            _ => None,
+        })
+        .collect();
+
+    let renamed_from = |key: &str| -> Option<String> {
+        let value = &new[key];
+        if value.is_empty() || added.iter().filter(|k| new[**k] == *value).count() != 1 {
+            return None;
+        }
+
+        match removed
+            .iter()
+            .filter(|old_key| old[*old_key] == *value)
+            .collect::<Vec<&String>>()
+            .as_slice()
+        {
+            [old_key] => Some(old_key.to_string()),
+            _ => None,
+        }
+    };
+    let renames: HashMap<String, String> = added
+        .iter()
+        .filter_map(|key| Some((key.to_string(), renamed_from(key)?)))
+        .collect();
+    removed.retain(|key| !renames.values().any(|old_key| old_key == key));
+
+    let changes: Vec<Change> = changes
+        .into_iter()
+        .map(|change| match change {
+            Change::Added(key) => match renames.get(&key) {
+                Some(old_key) => Change::Renamed(old_key.to_string(), key),
+                None => Change::Added(key),
+            },
+            change => change,
+        })
+        .collect();
+
+    // Removed keys that were matched to an added key are now reported as renamed:
+    let mut changes: Vec<Change> = changes
+        .into_iter()
+        .filter(|change| match change {
+            Change::Removed(key) => removed.contains(key),
+            _ => true,
+        })
+        .collect();
+
+    changes.sort_by(|a, b| a.key().cmp(b.key()));
+    changes
+}
+
+/// Prints a list of changes, including the values of the keys that changed when provided.
+pub fn print_changes(
+    changes: &[Change],
+    old_values: Option<&HashMap<String, String>>,
+    new_values: Option<&HashMap<String, String>>,
+) {
+    for change in changes.iter() {
+        match change {
+            Change::Added(key) => {
+                println!("{}", format!("+ {}", key).green());
+                if let Some(new_values) = new_values {
+                    println!("    {}", new_values[key]);
+                }
+            }
+            Change::Removed(key) => {
+                println!("{}", format!("- {}", key).red());
+                if let Some(old_values) = old_values {
+                    println!("    {}", old_values[key]);
+                }
+            }
+            Change::Changed(key) => {
+                println!("{}", format!("~ {}", key).yellow());
+                if let (Some(old_values), Some(new_values)) = (old_values, new_values) {
+                    println!("    {} {}", "-".red(), old_values[key]);
+                    println!("    {} {}", "+".green(), new_values[key]);
+                }
+            }
+            Change::Renamed(old_key, key) => {
+                println!("{}", format!("> {} -> {}", old_key, key).blue());
+                if let Some(new_values) = new_values {
+                    println!("    {}", new_values[key]);
+                }
+            }
+        }
+    }
+}
+
+/// Prints the changes that were made to an encrypted configuration. Unchanged values keep
+/// their previous ciphertext, so the encrypted values can be compared directly.
+pub fn print_encrypted_changes(
+    previous: &HashMap<String, String>,
+    current: &HashMap<String, String>,
+) {
+    let changes = detect_renames(diff_configurations(previous, current), previous, current);
+
+    if changes.is_empty() {
+        println!("No secrets were changed.");
+    } else {
+        print_changes(&changes, None, None);
+    }
+}
+
+fn read_revision(path: &Path, rev: &str) -> Result<String, &'static str> {
+    let directory = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let file_name = match path.file_name().and_then(|name| name.to_str()) {
+        Some(name) => name,
+        None => return Err("The provided configuration file path is not valid."),
+    };
+
+    let output = match Command::new("git")
+        .arg("-C")
+        .arg(directory)
+        .arg("show")
+        .arg(format!("{}:./{}", rev, file_name))
+        .output()
+    {
+        Ok(val) => val,
+        Err(_) => return Err("Failed to run git, please verify that it is installed."),
+    };
+
+    if !output.status.success() {
+        return Err("Unable to read the configuration file at the provided revision.");
+    }
+
+    match String::from_utf8(output.stdout) {
+        Ok(val) => Ok(val),
+        Err(_) => Err("The configuration file at the provided revision was not utf8 encoded."),
+    }
+}
+
+fn decrypt(config: &Config) -> Result<HashMap<String, String>, &'static str> {
+    Encryption { config }.decrypt_configuration()
+}
+
+pub fn diff(cmd: &Diff) -> Result<(), &'static str> {
+    if !Config::exists(&cmd.file) {
+        return Err("The provided configuration file does not exist");
+    }
+
+    let (old_config, new_config) = match (&cmd.other, &cmd.rev) {
+        (Some(_), Some(_)) => {
+            return Err("Either a second configuration file or '--rev' can be provided, not both.")
+        }
+        (None, None) => {
+            return Err("A second configuration file or '--rev' must be provided.");
+        }
+        (Some(other), None) => {
+            if !Config::exists(other) {
+                return Err("The provided configuration file does not exist");
+            }
+            (Config::get(&cmd.file), Config::get(other))
+        }
+        (None, Some(rev)) => (
+            Config::from_yaml(&read_revision(&cmd.file, rev)?)?,
+            Config::get(&cmd.file),
+        ),
+    };
+
+    let old_values = decrypt(&old_config)?;
+    let new_values = decrypt(&new_config)?;
+    let changes = detect_renames(
+        diff_configurations(&old_values, &new_values),
+        &old_values,
+        &new_values,
+    );
+
+    if changes.is_empty() {
+        println!("No changes.");
+        return Ok(());
+    }
+
+    if cmd.show_values {
+        print_changes(&changes, Some(&old_values), Some(&new_values));
+    } else {
+        print_changes(&changes, None, None);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn get_secrets_path() -> PathBuf {
+        let mut path = env::current_dir().unwrap();
+        path.push("test");
+        path.push("secrets.yml");
+        path
+    }
+
+    #[test]
+    fn test_diff_configurations() {
+        let mut old: HashMap<String, String> = HashMap::new();
+        old.insert("SAME".to_string(), "value".to_string());
+        old.insert("CHANGED".to_string(), "old value".to_string());
+        old.insert("REMOVED".to_string(), "value".to_string());
+
+        let mut new: HashMap<String, String> = HashMap::new();
+        new.insert("SAME".to_string(), "value".to_string());
+        new.insert("CHANGED".to_string(), "new value".to_string());
+        new.insert("ADDED".to_string(), "value".to_string());
+
+        assert_eq!(
+            diff_configurations(&old, &new),
+            vec![
+                Change::Added("ADDED".to_string()),
+                Change::Changed("CHANGED".to_string()),
+                Change::Removed("REMOVED".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_detect_renames() {
+        let mut old: HashMap<String, String> = HashMap::new();
+        old.insert("OLD_NAME".to_string(), "value".to_string());
+        old.insert("REMOVED".to_string(), "other value".to_string());
+
+        let mut new: HashMap<String, String> = HashMap::new();
+        new.insert("NEW_NAME".to_string(), "value".to_string());
+
+        assert_eq!(
+            detect_renames(diff_configurations(&old, &new), &old, &new),
+            vec![
+                Change::Renamed("OLD_NAME".to_string(), "NEW_NAME".to_string()),
+                Change::Removed("REMOVED".to_string()),
+            ]
+        );
+
+        // Empty and shared values don't make unrelated keys a rename:
+        old.insert("OLD_EMPTY".to_string(), "".to_string());
+        new.insert("NEW_EMPTY".to_string(), "".to_string());
+        old.insert("OLD_ENABLED".to_string(), "true".to_string());
+        old.insert("OLD_DEBUG".to_string(), "true".to_string());
+        new.insert("NEW_ENABLED".to_string(), "true".to_string());
+
+        assert_eq!(
+            detect_renames(diff_configurations(&old, &new), &old, &new),
+            vec![
+                Change::Added("NEW_EMPTY".to_string()),
+                Change::Added("NEW_ENABLED".to_string()),
+                Change::Renamed("OLD_NAME".to_string(), "NEW_NAME".to_string()),
+                Change::Removed("OLD_DEBUG".to_string()),
+                Change::Removed("OLD_EMPTY".to_string()),
+                Change::Removed("OLD_ENABLED".to_string()),
+                Change::Removed("REMOVED".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diff_reencrypted_values() -> Result<(), &'static str> {
+        let config = Config::get(&get_secrets_path());
+        let encryption = Encryption { config: &config };
+
+        let mut reencrypted = config.clone();
+        for (key, value) in encryption.decrypt_configuration()? {
+            reencrypted
+                .configuration
+                .insert(key.to_string(), encryption.encrypt(&key, &value)?);
+        }
+
+        assert!(diff_configurations(&decrypt(&config)?, &decrypt(&reencrypted)?).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_requires_comparison() {
+        assert!(diff(&Diff {
+            show_values: false,
+            rev: None,
+            file: get_secrets_path(),
+            other: None,
+        })
+        .is_err());
+    }
+}
@@ -0,0 +1,225 @@
+use crate::private::PrivateDirectory;
+use std::env;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+#[cfg(unix)]
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// A file in a private temporary directory that holds decrypted secrets while they are being
+/// edited. The file, and anything the editor left next to it, is overwritten before it is
+/// removed, which happens when it is dropped.
+pub struct SecretsFile {
+    // Held so that the directory is removed when it is dropped:
+    _directory: PrivateDirectory,
+    path: PathBuf,
+}
+
+impl SecretsFile {
+    pub fn create(contents: &str) -> std::io::Result<SecretsFile> {
+        let directory = PrivateDirectory::create()?;
+        let path = directory.write_file("secrets.yml", contents.as_bytes(), 0o600)?;
+
+        Ok(SecretsFile {
+            _directory: directory,
+            path,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn read(&self) -> std::io::Result<String> {
+        std::fs::read_to_string(&self.path)
+    }
+}
+
+/// Arguments that stop editors from writing swap, undo, backup, and history files, which
+/// would otherwise keep copies of the decrypted secrets around. Vim runs `-c` commands after
+/// the user's vimrc, so a vimrc can't turn backups or undo files back on.
+fn private_editor_args(program: &str) -> Vec<&'static str> {
+    let name = Path::new(program)
+        .file_stem()
+        .and_then(|name| name.to_str())
+        .unwrap_or("");
+
+    match name {
+        "vi" | "vim" | "nvim" | "gvim" | "mvim" => vec![
+            "-n",
+            "-i",
+            "NONE",
+            "-c",
+            "set nobackup nowritebackup noundofile",
+        ],
+        "nano" => vec!["--ignorercfiles"],
+        "emacs" => vec![
+            "--eval",
+            "(setq make-backup-files nil auto-save-default nil create-lockfiles nil)",
+        ],
+        _ => vec![],
+    }
+}
+
+fn editor_command(path: &Path) -> Result<Command, &'static str> {
+    let editor = env::var("EDITOR").unwrap_or_default();
+    let mut parts = editor.split_whitespace();
+
+    let program = match parts.next() {
+        Some(val) => val,
+        None => return Err(
+            "You must define your $EDITOR environment variable to edit a Scoob configuration file.",
+        ),
+    };
+
+    let mut command = Command::new(program);
+    command
+        .args(private_editor_args(program))
+        .args(parts)
+        .arg(path);
+    Ok(command)
+}
+
+#[cfg(unix)]
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+extern "C" fn on_interrupt(_: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+/// Catches interrupts while the editor is open, so that scoob outlives the editor and is able
+/// to remove the temporary file. The previous signal handlers are restored when dropped.
+#[cfg(unix)]
+struct InterruptGuard {
+    previous: Vec<(libc::c_int, libc::sigaction)>,
+}
+
+#[cfg(unix)]
+impl InterruptGuard {
+    fn new() -> InterruptGuard {
+        INTERRUPTED.store(false, Ordering::SeqCst);
+        let mut previous = Vec::new();
+
+        for signal in [libc::SIGINT, libc::SIGTERM, libc::SIGHUP].iter() {
+            unsafe {
+                let mut action: libc::sigaction = std::mem::zeroed();
+                action.sa_sigaction = on_interrupt as extern "C" fn(libc::c_int) as usize;
+                libc::sigemptyset(&mut action.sa_mask);
+
+                let mut previous_action: libc::sigaction = std::mem::zeroed();
+                if libc::sigaction(*signal, &action, &mut previous_action) == 0 {
+                    previous.push((*signal, previous_action));
+                }
+            }
+        }
+
+        InterruptGuard { previous }
+    }
+
+    fn interrupted(&self) -> bool {
+        INTERRUPTED.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(unix)]
+impl Drop for InterruptGuard {
+    fn drop(&mut self) {
+        for (signal, action) in self.previous.iter() {
+            unsafe {
+                libc::sigaction(*signal, action, std::ptr::null_mut());
+            }
+        }
+    }
+}
+
+/// Opens the contents in the user's editor, and returns the edited contents.
+pub fn edit_secrets(contents: &str) -> Result<String, &'static str> {
+    let file = match SecretsFile::create(contents) {
+        Ok(val) => val,
+        Err(_) => return Err("Unable to create a temporary file to edit the secrets in."),
+    };
+
+    let mut command = editor_command(file.path())?;
+
+    #[cfg(unix)]
+    let guard = InterruptGuard::new();
+
+    let status = command.status();
+
+    #[cfg(unix)]
+    {
+        if guard.interrupted() {
+            return Err("Editing was interrupted, no changes were made.");
+        }
+    }
+
+    match status {
+        Ok(status) if status.success() => (),
+        Ok(_) => return Err("The editor exited with an error, no changes were made."),
+        Err(_) => return Err("Failed to start the editor, please verify that $EDITOR exists."),
+    };
+
+    match file.read() {
+        Ok(val) => Ok(val),
+        Err(_) => Err("Unable to read the edited secrets."),
+    }
+}
+
+/// Asks the user a yes or no question, defaulting to yes.
+pub fn confirm(question: &str) -> bool {
+    print!("{} [Y/n] ", question);
+    std::io::stdout().flush().ok();
+
+    let mut answer = String::new();
+    match std::io::stdin().read_line(&mut answer) {
+        // Nothing can be read, which means there's nobody to answer:
+        Ok(0) | Err(_) => false,
+        Ok(_) => !matches!(answer.trim().to_lowercase().as_str(), "n" | "no"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(unix)]
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn test_secrets_file() -> std::io::Result<()> {
+        let file = SecretsFile::create("SECRET: value")?;
+        let path = file.path().to_path_buf();
+        assert_eq!(file.read()?, "SECRET: value");
+
+        #[cfg(unix)]
+        {
+            let file_mode = std::fs::metadata(&path)?.permissions().mode();
+            let directory_mode = std::fs::metadata(path.parent().unwrap())?
+                .permissions()
+                .mode();
+            assert_eq!(file_mode & 0o777, 0o600);
+            assert_eq!(directory_mode & 0o777, 0o700);
+        }
+
+        // Files left behind by the editor are removed too:
+        let backup_path = path.with_extension("yml~");
+        std::fs::write(&backup_path, "SECRET: value")?;
+
+        drop(file);
+        assert!(!path.exists());
+        assert!(!backup_path.exists());
+        assert!(!path.parent().unwrap().exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_private_editor_args() {
+        assert!(private_editor_args("/usr/bin/vim").contains(&"-n"));
+        assert!(private_editor_args("nvim").contains(&"-n"));
+        assert!(private_editor_args("vi").contains(&"-c"));
+        assert!(!private_editor_args("vim").contains(&"--cmd"));
+        assert_eq!(private_editor_args("nano"), vec!["--ignorercfiles"]);
+        assert_eq!(private_editor_args("emacs").len(), 2);
+        assert!(private_editor_args("code").is_empty());
+    }
+}
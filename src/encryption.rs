@@ -16,15 +16,20 @@ fn resolve_key(key: &str) -> String {
         let key_type = parts.next();
         let key_data = parts.next();
 
-        if key_type == Some("env") || key_type == Some("") || key_type == None {
+        if key_type == Some("env") || key_type == Some("") || key_type.is_none() {
             let value = key_data.and_then(|k| env::var(k).ok());
             return value.unwrap_or_else(|| "".into());
         } else if key_type == Some("awsSecretsManager") {
-            if key_data == None {
+            if key_data.is_none() {
                 return "".into();
             }
 
-            let client = SecretsManagerClient::new(rusoto_core::Region::UsEast1);
+            // The region can be set with SCOOB_AWS_REGION, which the project file sets too:
+            let region = env::var("SCOOB_AWS_REGION")
+                .ok()
+                .and_then(|region| region.parse().ok())
+                .unwrap_or(rusoto_core::Region::UsEast1);
+            let client = SecretsManagerClient::new(region);
             let request = GetSecretValueRequest {
                 secret_id: key_data.unwrap().into(),
                 ..Default::default()
@@ -46,6 +51,27 @@ fn resolve_key(key: &str) -> String {
     key.to_string()
 }
 
+/// Returns the name of the environment variable that a key is read from, if any.
+fn key_variable(key: &str) -> Option<&str> {
+    let mut parts = key.splitn(2, '$');
+    match (parts.next(), parts.next()) {
+        (Some("env"), Some(name)) | (Some(""), Some(name)) => Some(name),
+        _ => None,
+    }
+}
+
+/// Returns the key whose encrypted value a placeholder refers to. Values are either left
+/// as `<encrypted>`, or moved from another key using `<encrypted:OLD_KEY>`.
+fn placeholder_source<'a>(key: &'a str, value: &'a str) -> Option<&'a str> {
+    if value == "<encrypted>" {
+        return Some(key);
+    }
+
+    value
+        .strip_prefix("<encrypted:")
+        .and_then(|v| v.strip_suffix('>'))
+}
+
 pub struct Encryption<'a> {
     pub config: &'a Config,
 }
@@ -60,46 +86,163 @@ impl Encryption<'_> {
     }
 
     pub fn encrypt_configuration(&self, new_config: &Config) -> Result<Config, &'static str> {
+        self.encrypt_configuration_with(new_config, &HashMap::new())
+    }
+
+    /// Encrypts the configuration, using values of the previous configuration that were
+    /// already decrypted, such as the values revealed in the editor, instead of decrypting
+    /// them again. Decrypting can require a request to a key provider for every value.
+    pub fn encrypt_configuration_with(
+        &self,
+        new_config: &Config,
+        decrypted: &HashMap<String, String>,
+    ) -> Result<Config, &'static str> {
         let mut encrypted_configuration: HashMap<String, String> = HashMap::new();
+        let mut previous_values: HashMap<String, Option<String>> = decrypted
+            .iter()
+            .map(|(key, value)| (key.to_string(), Some(value.to_string())))
+            .collect();
 
         let new_encrypter = Encryption { config: new_config };
 
         for (key, value) in new_config.configuration.iter() {
-            match value.as_str() {
-                // Encrypted value that has not changed. We just use the previous value:
-                "<encrypted>" => {
-                    let previous_value = self.config.configuration.get(key);
-                    if previous_value.is_none() {
-                        return Err("Encrypted values cannot be moved or renamed.");
-                    }
+            match placeholder_source(key, value) {
+                // Encrypted value that has not changed, but may have been moved or renamed:
+                Some(previous_key) => {
+                    let previous_value = match self.config.configuration.get(previous_key) {
+                        Some(val) => val,
+                        None if previous_key == key => return Err(
+                            "Encrypted values can only be moved or renamed using the '<encrypted:OLD_KEY>' placeholder.",
+                        ),
+                        None => return Err(
+                            "An '<encrypted:OLD_KEY>' placeholder refers to a key that does not exist.",
+                        ),
+                    };
 
                     encrypted_configuration.insert(
                         key.to_string(),
-                        previous_value
-                            .expect("Previous value is missing.")
-                            .to_string(),
+                        self.rewrap(&new_encrypter, previous_key, key, previous_value)?,
                     )
                 }
-                // New value:
-                _ => encrypted_configuration
-                    .insert(key.to_string(), new_encrypter.encrypt(key, value)?),
+                // New value, which may match a value we already have encrypted:
+                None => {
+                    let encrypted_value =
+                        match self.find_unchanged(&new_encrypter, &mut previous_values, key, value)
+                        {
+                            Some(previous_value) => previous_value,
+                            None => new_encrypter.encrypt(key, value)?,
+                        };
+
+                    encrypted_configuration.insert(key.to_string(), encrypted_value)
+                }
             };
         }
 
         Ok(Config {
             configuration: encrypted_configuration,
             keys: new_config.keys.clone(),
+            manifest: new_config.manifest.clone(),
+            files: new_config.files.clone(),
+            env: new_config.env.clone(),
         })
     }
 
+    /// Finds the previous ciphertext for a value that did not change, so that it can be
+    /// kept instead of re-encrypted. The value previously stored under the same key is
+    /// preferred, falling back to keys that were removed, which covers keys being renamed.
+    /// Only those values are decrypted, once each, and values that can't be decrypted with
+    /// the keys currently available are skipped.
+    fn find_unchanged(
+        &self,
+        new_encrypter: &Encryption,
+        previous_values: &mut HashMap<String, Option<String>>,
+        key: &str,
+        value: &str,
+    ) -> Option<String> {
+        let mut removed_keys: Vec<&String> = self
+            .config
+            .configuration
+            .keys()
+            .filter(|k| !new_encrypter.config.configuration.contains_key(*k))
+            .collect();
+        removed_keys.sort();
+
+        std::iter::once(key)
+            .chain(removed_keys.into_iter().map(|k| k.as_str()))
+            .filter(|previous_key| self.same_key_entry(previous_key, new_encrypter, key))
+            .filter_map(|previous_key| {
+                let ciphertext = self.config.configuration.get(previous_key)?;
+                let previous_value = previous_values
+                    .entry(previous_key.to_string())
+                    .or_insert_with(|| self.decrypt(previous_key, ciphertext).ok());
+
+                match previous_value {
+                    Some(previous_value) if previous_value == value => Some(ciphertext.clone()),
+                    _ => None,
+                }
+            })
+            .next()
+    }
+
+    /// Moves an encrypted value to a new key. The ciphertext is kept when the same `keys` entry
+    /// applies to both keys, otherwise the value is decrypted and encrypted for the new entry.
+    fn rewrap(
+        &self,
+        new_encrypter: &Encryption,
+        previous_key: &str,
+        key: &str,
+        previous_value: &str,
+    ) -> Result<String, &'static str> {
+        if self.same_key_entry(previous_key, new_encrypter, key) {
+            return Ok(previous_value.to_string());
+        }
+
+        let decrypted = match self.decrypt(previous_key, previous_value) {
+            Ok(val) => val,
+            Err(_) => return Err(
+                "Encrypted values that are moved to a different encryption key must be decrypted, but the secret key is not available.",
+            ),
+        };
+
+        new_encrypter.encrypt(key, &decrypted)
+    }
+
+    fn same_key_entry(&self, key: &str, other: &Encryption, other_key: &str) -> bool {
+        self.key_entry(key).map(|k| &k.public_key)
+            == other.key_entry(other_key).map(|k| &k.public_key)
+    }
+
+    pub fn decrypt_configuration(&self) -> Result<HashMap<String, String>, &'static str> {
+        let mut decrypted_configuration: HashMap<String, String> = HashMap::new();
+
+        for (key, value) in self.config.configuration.iter() {
+            decrypted_configuration.insert(key.to_string(), self.decrypt(key, value)?);
+        }
+
+        Ok(decrypted_configuration)
+    }
+
     pub fn encrypt(&self, key: &str, value: &str) -> Result<String, &'static str> {
+        self.encrypt_bytes(key, value.as_bytes())
+    }
+
+    pub fn decrypt(&self, key: &str, value: &str) -> Result<String, &'static str> {
+        let decrypted = self.decrypt_bytes(key, value)?;
+
+        Ok(match str::from_utf8(&decrypted) {
+            Ok(val) => val.to_string(),
+            Err(_) => return Err("Secret was not utf8 encoded"),
+        })
+    }
+
+    pub fn encrypt_bytes(&self, key: &str, value: &[u8]) -> Result<String, &'static str> {
         let public_key = self.get_pub_key(key)?;
 
-        let message = sealedbox::seal(value.as_bytes(), &public_key);
+        let message = sealedbox::seal(value, &public_key);
         Ok(BASE64.encode(&message))
     }
 
-    pub fn decrypt(&self, key: &str, value: &str) -> Result<String, &'static str> {
+    pub fn decrypt_bytes(&self, key: &str, value: &str) -> Result<Vec<u8>, &'static str> {
         let public_key = self.get_pub_key(key)?;
         let secret_key = self.get_sec_key(key)?;
 
@@ -108,24 +251,36 @@ impl Encryption<'_> {
             Err(_) => return Err("Secret was not base64 encoded."),
         };
 
-        let decrypted = match sealedbox::open(&decoded, &public_key, &secret_key) {
-            Ok(val) => val,
-            Err(_) => return Err("Failed to decrypt secret."),
-        };
-
-        Ok(match str::from_utf8(&decrypted) {
-            Ok(val) => val.to_string(),
-            Err(_) => return Err("Secret was not utf8 encoded"),
-        })
+        match sealedbox::open(&decoded, &public_key, &secret_key) {
+            Ok(val) => Ok(val),
+            Err(_) => Err("Failed to decrypt secret."),
+        }
     }
 
-    fn resolve_keys(&self, key: &str) -> Result<EncryptionKey, &'static str> {
-        let keys = match self
+    /// The environment variables that the encryption keys are read from.
+    pub fn key_variables(&self) -> Vec<String> {
+        let mut variables: Vec<String> = self
             .config
+            .keys
+            .values()
+            .flat_map(|keys| vec![&keys.public_key, &keys.secret_key])
+            .filter_map(|key| key_variable(key))
+            .map(|name| name.to_string())
+            .collect();
+        variables.sort();
+        variables.dedup();
+        variables
+    }
+
+    fn key_entry(&self, key: &str) -> Option<&EncryptionKey> {
+        self.config
             .keys
             .get(key)
             .or_else(|| self.config.keys.get("*"))
-        {
+    }
+
+    fn resolve_keys(&self, key: &str) -> Result<EncryptionKey, &'static str> {
+        let keys = match self.key_entry(key) {
             Some(val) => val,
             None => return Err("Missing encryption keys."),
         };
@@ -141,15 +296,13 @@ impl Encryption<'_> {
 
     fn get_sec_key(&self, key: &str) -> Result<SecretKey, &'static str> {
         let sec_key = self.resolve_keys(key)?.secret_key;
-        let seckey_decoded: Vec<u8>;
-
-        if sec_key.as_bytes().len() == 64 {
-            seckey_decoded = HEXUPPER_PERMISSIVE
+        let seckey_decoded: Vec<u8> = if sec_key.len() == 64 {
+            HEXUPPER_PERMISSIVE
                 .decode(sec_key.as_bytes())
-                .unwrap_or_default();
+                .unwrap_or_default()
         } else {
-            seckey_decoded = BASE64.decode(sec_key.as_bytes()).unwrap_or_default();
-        }
+            BASE64.decode(sec_key.as_bytes()).unwrap_or_default()
+        };
 
         if seckey_decoded.len() != SECRETKEYBYTES {
             return Err("The secret key did not match the expected format.");
@@ -163,15 +316,13 @@ impl Encryption<'_> {
 
     fn get_pub_key(&self, key: &str) -> Result<PublicKey, &'static str> {
         let pub_key = self.resolve_keys(key)?.public_key;
-        let pubkey_decoded: Vec<u8>;
-
-        if pub_key.as_bytes().len() == 64 {
-            pubkey_decoded = HEXUPPER_PERMISSIVE
+        let pubkey_decoded: Vec<u8> = if pub_key.len() == 64 {
+            HEXUPPER_PERMISSIVE
                 .decode(pub_key.as_bytes())
-                .unwrap_or_default();
+                .unwrap_or_default()
         } else {
-            pubkey_decoded = BASE64.decode(pub_key.as_bytes()).unwrap_or_default();
-        }
+            BASE64.decode(pub_key.as_bytes()).unwrap_or_default()
+        };
 
         if pubkey_decoded.len() != PUBLICKEYBYTES {
             return Err("The public key did not match the expected format.");
@@ -206,7 +357,7 @@ mod tests {
 
         let encrypted_config = enc.encrypt_configuration(&new_config)?;
 
-        let encrypted_key = encrypted_config.configuration.get(&"NEW_KEY".to_string());
+        let encrypted_key = encrypted_config.configuration.get("NEW_KEY");
         assert!(encrypted_key.is_some());
         assert_ne!(encrypted_key.unwrap(), new_value);
 
@@ -222,6 +373,137 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_encrypt_configuration_unchanged() -> Result<(), &'static str> {
+        let mut path = env::current_dir().unwrap();
+        path.push("test");
+        path.push("secrets.yml");
+        let original_config = Config::get(&path);
+        let enc = Encryption {
+            config: &original_config,
+        };
+
+        let mut new_config = original_config.clone();
+        new_config
+            .configuration
+            .insert("TEST_KEY".to_string(), "another encrypted key".to_string());
+        new_config.configuration.remove("EXAMPLE_KEY");
+        new_config.configuration.insert(
+            "RENAMED_KEY".to_string(),
+            enc.decrypt("EXAMPLE_KEY", &original_config.configuration["EXAMPLE_KEY"])?,
+        );
+
+        let encrypted_config = enc.encrypt_configuration(&new_config)?;
+
+        assert_eq!(
+            encrypted_config.configuration["TEST_KEY"],
+            original_config.configuration["TEST_KEY"]
+        );
+        assert_eq!(
+            encrypted_config.configuration["RENAMED_KEY"],
+            original_config.configuration["EXAMPLE_KEY"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_encrypt_configuration_with_decrypted() -> Result<(), &'static str> {
+        let mut path = env::current_dir().unwrap();
+        path.push("test");
+        path.push("secrets.yml");
+        let mut original_config = Config::get(&path);
+        original_config
+            .configuration
+            .insert("TEST_KEY".to_string(), "not a ciphertext".to_string());
+        let enc = Encryption {
+            config: &original_config,
+        };
+
+        // Values that were already decrypted are compared without decrypting them again:
+        let mut new_config = original_config.with_placeholders();
+        new_config
+            .configuration
+            .insert("TEST_KEY".to_string(), "revealed".to_string());
+        let mut decrypted = HashMap::new();
+        decrypted.insert("TEST_KEY".to_string(), "revealed".to_string());
+
+        let encrypted_config = enc.encrypt_configuration_with(&new_config, &decrypted)?;
+        assert_eq!(
+            encrypted_config.configuration["TEST_KEY"],
+            "not a ciphertext"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_encrypt_configuration_rename() -> Result<(), &'static str> {
+        let mut path = env::current_dir().unwrap();
+        path.push("test");
+        path.push("secrets.yml");
+        let original_config = Config::get(&path);
+        let enc = Encryption {
+            config: &original_config,
+        };
+
+        let (public_key, secret_key) = Encryption::gen_keypair();
+        let mut new_config = original_config.with_placeholders();
+        new_config.configuration.remove("TEST_KEY");
+        new_config.configuration.remove("EXAMPLE_KEY");
+        new_config
+            .configuration
+            .insert("MOVED_KEY".to_string(), "<encrypted:TEST_KEY>".to_string());
+        new_config.configuration.insert(
+            "REWRAPPED_KEY".to_string(),
+            "<encrypted:EXAMPLE_KEY>".to_string(),
+        );
+        new_config.keys.insert(
+            "REWRAPPED_KEY".to_string(),
+            EncryptionKey {
+                public_key,
+                secret_key,
+            },
+        );
+
+        let encrypted_config = enc.encrypt_configuration(&new_config)?;
+        let new_enc = Encryption {
+            config: &encrypted_config,
+        };
+
+        assert_eq!(
+            encrypted_config.configuration["MOVED_KEY"],
+            original_config.configuration["TEST_KEY"]
+        );
+        assert_eq!(
+            new_enc.decrypt(
+                "REWRAPPED_KEY",
+                &encrypted_config.configuration["REWRAPPED_KEY"]
+            )?,
+            enc.decrypt("EXAMPLE_KEY", &original_config.configuration["EXAMPLE_KEY"])?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_encrypt_configuration_rename_missing() {
+        let original_config = Config::default();
+        let enc = Encryption {
+            config: &original_config,
+        };
+
+        let mut new_config = original_config.clone();
+        new_config
+            .configuration
+            .insert("MOVED_KEY".to_string(), "<encrypted>".to_string());
+        assert!(enc.encrypt_configuration(&new_config).is_err());
+
+        new_config
+            .configuration
+            .insert("MOVED_KEY".to_string(), "<encrypted:MISSING>".to_string());
+        assert!(enc.encrypt_configuration(&new_config).is_err());
+    }
+
     #[test]
     fn test_encrypt_decrypt() -> Result<(), &'static str> {
         let mut path = env::current_dir().unwrap();
@@ -236,11 +518,47 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_encrypt_decrypt_bytes() -> Result<(), &'static str> {
+        let config = Config::default();
+        let enc = Encryption { config: &config };
+        let raw_value: Vec<u8> = vec![0, 159, 146, 150, 255];
+        let encrypted = enc.encrypt_bytes("test", &raw_value)?;
+        assert_eq!(enc.decrypt_bytes("test", &encrypted)?, raw_value);
+        assert!(enc.decrypt("test", &encrypted).is_err());
+        Ok(())
+    }
+
     #[test]
     fn test_env_key() {
         // TODO: Write test for env variable keys.
     }
 
+    #[test]
+    fn test_key_variables() {
+        let mut config = Config::default();
+        config.keys.insert(
+            "API_KEY".to_string(),
+            EncryptionKey {
+                public_key: "env$SCOOB_PUBLIC_KEY".to_string(),
+                secret_key: "$SCOOB_SECRET_KEY".to_string(),
+            },
+        );
+        config.keys.insert(
+            "AWS".to_string(),
+            EncryptionKey {
+                public_key: "env$SCOOB_PUBLIC_KEY".to_string(),
+                secret_key: "awsSecretsManager$scoob".to_string(),
+            },
+        );
+
+        let encryption = Encryption { config: &config };
+        assert_eq!(
+            encryption.key_variables(),
+            vec!["SCOOB_PUBLIC_KEY", "SCOOB_SECRET_KEY"]
+        );
+    }
+
     #[test]
     fn test_missing_keys() {
         let mut config = Config::default();
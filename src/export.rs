@@ -0,0 +1,427 @@
+use crate::config::Config;
+use crate::encryption::Encryption;
+use crate::hook::{allow_list_path, file_hash, find_secrets_file, is_allowed};
+use colored::Colorize;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use structopt::StructOpt;
+
+/// The environment variables that the hook keeps its state in, in the shell that it runs in.
+const HOOK_FILE: &str = "SCOOB_HOOK_FILE";
+const HOOK_HASH: &str = "SCOOB_HOOK_HASH";
+const HOOK_KEYS: &str = "SCOOB_HOOK_KEYS";
+const HOOK_BLOCKED: &str = "SCOOB_HOOK_BLOCKED";
+/// The values that the loaded secrets replaced, as a JSON object, which are restored when the
+/// secrets are unloaded.
+const HOOK_PREVIOUS: &str = "SCOOB_HOOK_PREVIOUS";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShellKind {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl FromStr for ShellKind {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<ShellKind, String> {
+        match value {
+            "bash" => Ok(ShellKind::Bash),
+            "zsh" => Ok(ShellKind::Zsh),
+            "fish" => Ok(ShellKind::Fish),
+            _ => Err(format!("Unsupported shell: {}", value)),
+        }
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub struct Export {
+    /// The shell to print the statements for
+    #[structopt(possible_values = &["bash", "zsh", "fish"])]
+    shell: ShellKind,
+    /// Path to the scoob configuration file. When it's left out, the nearest allowed secrets
+    /// file is loaded, and secrets loaded for another directory are unloaded, which is what
+    /// `scoob hook` uses
+    #[structopt(parse(from_os_str))]
+    file: Option<PathBuf>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Statement {
+    Set(String, String),
+    Unset(String),
+}
+
+fn is_variable_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+pub fn quote(shell: ShellKind, value: &str) -> String {
+    match shell {
+        ShellKind::Bash | ShellKind::Zsh => format!("'{}'", value.replace('\'', "'\\''")),
+        ShellKind::Fish => format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'")),
+    }
+}
+
+fn format_statements(shell: ShellKind, statements: &[Statement]) -> String {
+    statements
+        .iter()
+        .map(|statement| match (shell, statement) {
+            (ShellKind::Fish, Statement::Set(name, value)) => {
+                format!("set -gx {} {};\n", name, quote(shell, value))
+            }
+            (ShellKind::Fish, Statement::Unset(name)) => format!("set -e {};\n", name),
+            (_, Statement::Set(name, value)) => {
+                format!("export {}={};\n", name, quote(shell, value))
+            }
+            (_, Statement::Unset(name)) => format!("unset {};\n", name),
+        })
+        .collect()
+}
+
+/// Decrypts the values of the secrets, named by their `as:` names in `env:` when they have one.
+fn decrypt_values(contents: &str) -> Result<HashMap<String, String>, &'static str> {
+    let config = Config::from_yaml(contents)?;
+    let values = Encryption { config: &config }.decrypt_configuration()?;
+
+    let mut renamed = HashMap::new();
+    for (key, value) in values.into_iter() {
+        let name = match config.env.get(&key) {
+            Some(entry) => entry.name.to_string(),
+            None => key,
+        };
+
+        if !is_variable_name(&name) {
+            return Err("The name of a secret is not a valid environment variable name.");
+        }
+        renamed.insert(name, value);
+    }
+
+    Ok(renamed)
+}
+
+fn read_file(path: &Path) -> Result<String, &'static str> {
+    match std::fs::read_to_string(path) {
+        Ok(val) => Ok(val),
+        Err(_) => Err("Unable to read the scoob configuration file."),
+    }
+}
+
+/// What the hook loaded into the shell, read from the environment of the shell.
+#[derive(Debug, Default)]
+struct HookState {
+    file: Option<String>,
+    hash: Option<String>,
+    keys: Vec<String>,
+    blocked: Option<String>,
+    previous: BTreeMap<String, String>,
+}
+
+impl HookState {
+    fn from_env() -> HookState {
+        HookState {
+            file: env::var(HOOK_FILE).ok(),
+            hash: env::var(HOOK_HASH).ok(),
+            keys: env::var(HOOK_KEYS)
+                .unwrap_or_default()
+                .split(':')
+                .filter(|key| !key.is_empty())
+                .map(String::from)
+                .collect(),
+            blocked: env::var(HOOK_BLOCKED).ok(),
+            previous: env::var(HOOK_PREVIOUS)
+                .ok()
+                .and_then(|previous| serde_json::from_str(&previous).ok())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Returns the statements that bring the shell from what the hook loaded before to the
+/// secrets of the nearest allowed file. Nothing is returned when nothing changed, so the hook
+/// is cheap to run before every prompt. Variables of the shell that the secrets replace are
+/// restored when the secrets are unloaded.
+fn hook_statements(
+    found: Option<PathBuf>,
+    allow_list: &Path,
+    state: &HookState,
+    environment: &HashMap<String, String>,
+) -> Result<Vec<Statement>, &'static str> {
+    let current = match found {
+        Some(path) => {
+            let contents = read_file(&path)?;
+            let hash = file_hash(&path, &contents);
+            Some((path, contents, hash))
+        }
+        None => None,
+    };
+
+    let mut statements = Vec::new();
+    let mut allowed = None;
+
+    // Messages are written to stderr, so that they aren't evaluated by the shell:
+    match &current {
+        Some((path, contents, hash)) if is_allowed(allow_list, hash)? => {
+            allowed = Some((path, contents, hash));
+            if state.blocked.is_some() {
+                statements.push(Statement::Unset(HOOK_BLOCKED.to_string()));
+            }
+        }
+        Some((path, _, hash)) if state.blocked.as_ref() != Some(hash) => {
+            eprintln!(
+                "{}",
+                format!(
+                    "scoob: {} is not allowed, run `scoob allow` to load it.",
+                    path.display()
+                )
+                .yellow()
+            );
+            statements.push(Statement::Set(HOOK_BLOCKED.to_string(), hash.to_string()));
+        }
+        Some(_) => (),
+        None if state.blocked.is_some() => {
+            statements.push(Statement::Unset(HOOK_BLOCKED.to_string()));
+        }
+        None => (),
+    }
+
+    if allowed.map(|(_, _, hash)| hash) == state.hash.as_ref() {
+        return Ok(statements);
+    }
+
+    // The environment of the shell after unloading, which the values that the new secrets
+    // replace are taken from:
+    let mut environment = environment.clone();
+
+    if let Some(file) = &state.file {
+        for key in state.keys.iter() {
+            match state.previous.get(key) {
+                Some(value) => {
+                    statements.push(Statement::Set(key.to_string(), value.to_string()));
+                    environment.insert(key.to_string(), value.to_string());
+                }
+                None => {
+                    statements.push(Statement::Unset(key.to_string()));
+                    environment.remove(key);
+                }
+            }
+        }
+        for name in [HOOK_FILE, HOOK_HASH, HOOK_KEYS, HOOK_PREVIOUS].iter() {
+            statements.push(Statement::Unset(name.to_string()));
+        }
+        eprintln!("{}", format!("scoob: unloaded {}", file).green());
+    }
+
+    if let Some((path, contents, hash)) = allowed {
+        // Files that can't be decrypted are recorded as loaded without any secrets, so that
+        // the error isn't repeated before every prompt:
+        let values = match decrypt_values(contents) {
+            Ok(val) => val,
+            Err(err) => {
+                eprintln!("{} {}", "scoob: unable to load secrets:".red().bold(), err);
+                HashMap::new()
+            }
+        };
+
+        let mut keys: Vec<&String> = values.keys().collect();
+        keys.sort();
+        let keys_value = keys
+            .iter()
+            .map(|key| key.as_str())
+            .collect::<Vec<&str>>()
+            .join(":");
+        let previous: BTreeMap<&String, &String> = keys
+            .iter()
+            .filter_map(|key| environment.get_key_value(*key))
+            .collect();
+        let previous_value = match serde_json::to_string(&previous) {
+            Ok(val) => val,
+            Err(_) => return Err("Unable to record the previous values of the secrets."),
+        };
+
+        for key in keys {
+            statements.push(Statement::Set(key.to_string(), values[key].to_string()));
+        }
+        statements.push(Statement::Set(
+            HOOK_FILE.to_string(),
+            path.to_string_lossy().to_string(),
+        ));
+        statements.push(Statement::Set(HOOK_HASH.to_string(), hash.to_string()));
+        statements.push(Statement::Set(HOOK_KEYS.to_string(), keys_value));
+        statements.push(Statement::Set(HOOK_PREVIOUS.to_string(), previous_value));
+        eprintln!("{}", format!("scoob: loaded {}", path.display()).green());
+    }
+
+    Ok(statements)
+}
+
+pub fn export(cmd: &Export) -> Result<(), &'static str> {
+    let statements = match &cmd.file {
+        Some(file) => {
+            if !Config::exists(file) {
+                return Err("The provided configuration file does not exist");
+            }
+
+            let mut values: Vec<(String, String)> =
+                decrypt_values(&read_file(file)?)?.into_iter().collect();
+            values.sort();
+            values
+                .into_iter()
+                .map(|(name, value)| Statement::Set(name, value))
+                .collect()
+        }
+        None => {
+            let current_dir = match env::current_dir() {
+                Ok(val) => val,
+                Err(_) => return Err("Unable to read the current directory."),
+            };
+
+            hook_statements(
+                find_secrets_file(&current_dir)?,
+                &allow_list_path()?,
+                &HookState::from_env(),
+                &env::vars_os()
+                    .filter_map(|(name, value)| {
+                        Some((name.into_string().ok()?, value.into_string().ok()?))
+                    })
+                    .collect(),
+            )?
+        }
+    };
+
+    print!("{}", format_statements(cmd.shell, &statements));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hook::allow;
+
+    fn get_secrets_path() -> PathBuf {
+        let mut path = env::current_dir().unwrap();
+        path.push("test");
+        path.push("secrets.yml");
+        path
+    }
+
+    #[test]
+    fn test_format_statements() {
+        let statements = vec![
+            Statement::Set("TOKEN".to_string(), "it's \\ secret".to_string()),
+            Statement::Unset("OLD_TOKEN".to_string()),
+        ];
+
+        assert_eq!(
+            format_statements(ShellKind::Bash, &statements),
+            "export TOKEN='it'\\''s \\ secret';\nunset OLD_TOKEN;\n"
+        );
+        assert_eq!(
+            format_statements(ShellKind::Fish, &statements),
+            "set -gx TOKEN 'it\\'s \\\\ secret';\nset -e OLD_TOKEN;\n"
+        );
+    }
+
+    #[test]
+    fn test_is_variable_name() {
+        assert!(is_variable_name("DATABASE_URL"));
+        assert!(is_variable_name("_private1"));
+        assert!(!is_variable_name("1PASSWORD"));
+        assert!(!is_variable_name("KEY; rm -rf /"));
+        assert!(!is_variable_name(""));
+    }
+
+    #[test]
+    fn test_hook_statements() -> Result<(), &'static str> {
+        let directory = tempfile::tempdir().unwrap();
+        let allow_list = directory.path().join("allow");
+        let path = get_secrets_path();
+        let hash = file_hash(&path, &read_file(&path)?);
+
+        let environment: HashMap<String, String> =
+            vec![("TEST_KEY".to_string(), "from the shell".to_string())]
+                .into_iter()
+                .collect();
+
+        // Files that aren't allowed are not loaded:
+        let statements = hook_statements(
+            Some(path.clone()),
+            &allow_list,
+            &HookState::default(),
+            &HashMap::new(),
+        )?;
+        assert_eq!(
+            statements,
+            vec![Statement::Set(HOOK_BLOCKED.to_string(), hash.clone())]
+        );
+
+        allow(&allow_list, &path, &hash)?;
+        let statements = hook_statements(
+            Some(path.clone()),
+            &allow_list,
+            &HookState {
+                blocked: Some(hash.clone()),
+                ..HookState::default()
+            },
+            &environment,
+        )?;
+        assert!(statements.contains(&Statement::Unset(HOOK_BLOCKED.to_string())));
+        assert!(statements.contains(&Statement::Set(
+            "TEST_KEY".to_string(),
+            "another encrypted key".to_string()
+        )));
+        assert!(statements.contains(&Statement::Set(
+            HOOK_KEYS.to_string(),
+            "EXAMPLE_KEY:TEST_KEY".to_string()
+        )));
+        assert!(statements.contains(&Statement::Set(
+            HOOK_PREVIOUS.to_string(),
+            "{\"TEST_KEY\":\"from the shell\"}".to_string()
+        )));
+
+        let loaded = HookState {
+            file: Some(path.to_string_lossy().to_string()),
+            hash: Some(hash),
+            keys: vec!["EXAMPLE_KEY".to_string(), "TEST_KEY".to_string()],
+            blocked: None,
+            previous: BTreeMap::new(),
+        };
+
+        // Nothing changes while the same file is loaded:
+        assert!(
+            hook_statements(Some(path.clone()), &allow_list, &loaded, &environment)?.is_empty()
+        );
+
+        // Leaving the directory unloads the secrets:
+        let statements = hook_statements(None, &allow_list, &loaded, &environment)?;
+        assert!(statements.contains(&Statement::Unset("TEST_KEY".to_string())));
+        assert!(statements.contains(&Statement::Unset(HOOK_FILE.to_string())));
+        assert!(statements.contains(&Statement::Unset(HOOK_PREVIOUS.to_string())));
+        assert!(!statements
+            .iter()
+            .any(|statement| matches!(statement, Statement::Set(_, _))));
+
+        // Variables that the secrets replaced are restored:
+        let loaded = HookState {
+            previous: vec![("TEST_KEY".to_string(), "from the shell".to_string())]
+                .into_iter()
+                .collect(),
+            ..loaded
+        };
+        let statements = hook_statements(None, &allow_list, &loaded, &environment)?;
+        assert!(statements.contains(&Statement::Set(
+            "TEST_KEY".to_string(),
+            "from the shell".to_string()
+        )));
+        assert!(statements.contains(&Statement::Unset("EXAMPLE_KEY".to_string())));
+        assert!(!statements.contains(&Statement::Unset("TEST_KEY".to_string())));
+        Ok(())
+    }
+}
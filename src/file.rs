@@ -1,13 +1,22 @@
 use crate::config::Config;
 use crate::encryption::Encryption;
-use std::path::PathBuf;
+use crate::lock::SecretsLock;
+use crate::manifest::{
+    decrypt_dir, encrypt_dir, sync, DecryptDirOptions, EncryptDirOptions, SyncOptions,
+};
+use crate::project::{config_file, Project};
+use crate::stream::{decrypt_stream, encrypt_stream, STREAM_PREFIX};
+use crate::write::AtomicFile;
+use std::ffi::OsStr;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
 use structopt::StructOpt;
 
 #[derive(Debug, StructOpt)]
 pub struct File {
-    /// Path to the scoob configuration file
+    /// Path to the scoob configuration file, which defaults to the file set in the project file
     #[structopt(parse(from_os_str))]
-    config: PathBuf,
+    config: Option<PathBuf>,
 
     #[structopt(subcommand)]
     cmd: FileMode,
@@ -15,78 +24,471 @@ pub struct File {
 
 #[derive(Debug, StructOpt)]
 struct EncryptOptions {
-    /// The encryption key from the config file that will be used
-    #[structopt(short, long, default_value = "*")]
-    key: String,
+    /// The encryption key from the config file that will be used, which defaults to the key
+    /// set in the project file, or `*`
+    #[structopt(short, long)]
+    key: Option<String>,
 
-    /// The raw, unencrypted file
+    /// Encrypt the file in chunks, which keeps memory usage flat for large files
+    #[structopt(short, long)]
+    stream: bool,
+
+    /// The raw, unencrypted file, or `-` to read from stdin
     #[structopt(parse(from_os_str))]
     from: PathBuf,
 
-    /// File that the encrypted file will be encrypted to
+    /// File that the encrypted file will be encrypted to, or `-` to write to stdout
     #[structopt(parse(from_os_str))]
     to: PathBuf,
 }
 
 #[derive(Debug, StructOpt)]
 struct DecryptOptions {
-    /// The encryption key from the config file that will be used
-    #[structopt(short, long, default_value = "*")]
-    key: String,
+    /// The encryption key from the config file that will be used, which defaults to the key
+    /// set in the project file, or `*`
+    #[structopt(short, long)]
+    key: Option<String>,
 
-    /// The encrypted file
+    /// The encrypted file, or `-` to read from stdin
     #[structopt(parse(from_os_str))]
     from: PathBuf,
 
-    /// File that the encrypted file will be decrypted to
+    /// File that the encrypted file will be decrypted to, or `-` to write to stdout
+    #[structopt(parse(from_os_str))]
+    to: PathBuf,
+}
+
+#[derive(Debug, StructOpt)]
+struct AddOptions {
+    /// The name of the file, which is the environment variable that `start` sets to its path
+    name: String,
+
+    /// The raw, unencrypted file, or `-` to read from stdin
+    #[structopt(parse(from_os_str))]
+    from: PathBuf,
+}
+
+#[derive(Debug, StructOpt)]
+struct ExtractOptions {
+    /// The name of the file in the config file
+    name: String,
+
+    /// File that the file will be decrypted to, or `-` to write to stdout
     #[structopt(parse(from_os_str))]
     to: PathBuf,
 }
 
 #[derive(Debug, StructOpt)]
 enum FileMode {
+    #[structopt(alias = "enc")]
     Encrypt(EncryptOptions),
+    #[structopt(alias = "dec")]
     Decrypt(DecryptOptions),
+    /// Encrypt a file into the `files` section of the config file
+    Add(AddOptions),
+    /// Decrypt a file from the `files` section of the config file
+    Extract(ExtractOptions),
+    /// Encrypt every file in a directory, and track them in the manifest of the config file
+    EncryptDir(EncryptDirOptions),
+    /// Decrypt the files in the manifest that are in a directory
+    DecryptDir(DecryptDirOptions),
+    /// Encrypt the files in the manifest again, when their raw contents changed
+    Sync(SyncOptions),
+}
+
+/// Files are decrypted into a directory by their name, and their paths are set in environment
+/// variables by their name, so names can't escape the directory or shadow a value.
+pub fn check_file_name(config: &Config, name: &str) -> Result<(), &'static str> {
+    if config.configuration.contains_key(name) {
+        return Err("Files can't have the same name as a value in the configuration.");
+    }
+
+    if Path::new(name).file_name() != Some(OsStr::new(name)) {
+        return Err("The names of files can't contain path separators.");
+    }
+
+    Ok(())
+}
+
+/// Prefixes that record whether an encrypted file holds text or binary contents. Files
+/// encrypted before these were added have no prefix, and hold text.
+const TEXT_PREFIX: &str = "text:";
+const BINARY_PREFIX: &str = "binary:";
+
+pub fn encrypt_contents(
+    encryption: &Encryption,
+    key: &str,
+    raw_contents: &[u8],
+) -> Result<String, &'static str> {
+    let prefix = if std::str::from_utf8(raw_contents).is_ok() {
+        TEXT_PREFIX
+    } else {
+        BINARY_PREFIX
+    };
+
+    Ok(format!(
+        "{}{}",
+        prefix,
+        encryption.encrypt_bytes(key, raw_contents)?
+    ))
+}
+
+pub fn decrypt_contents(
+    encryption: &Encryption,
+    key: &str,
+    encrypted_contents: &str,
+) -> Result<Vec<u8>, &'static str> {
+    let encrypted_contents = encrypted_contents.trim();
+
+    if let Some(encrypted) = encrypted_contents.strip_prefix(BINARY_PREFIX) {
+        return encryption.decrypt_bytes(key, encrypted);
+    }
+
+    let encrypted = encrypted_contents
+        .strip_prefix(TEXT_PREFIX)
+        .unwrap_or(encrypted_contents);
+    Ok(encryption.decrypt(key, encrypted)?.into_bytes())
+}
+
+/// Files can be read from stdin, and written to stdout, by using `-` as their path.
+fn is_std_stream(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+fn open_input(path: &Path) -> Result<Box<dyn Read>, &'static str> {
+    if is_std_stream(path) {
+        return Ok(Box::new(std::io::stdin()));
+    }
+
+    match std::fs::File::open(path) {
+        Ok(val) => Ok(Box::new(val)),
+        Err(_) => Err("Unable to read file"),
+    }
+}
+
+/// Where the output of a command is written to. Decrypted contents written to stdout never
+/// touch the filesystem.
+enum Output {
+    Stdout(std::io::Stdout),
+    File(AtomicFile),
+}
+
+impl Output {
+    fn create(path: &Path, private: bool) -> Result<Output, &'static str> {
+        if is_std_stream(path) {
+            return Ok(Output::Stdout(std::io::stdout()));
+        }
+
+        match AtomicFile::create(path, private) {
+            Ok(val) => Ok(Output::File(val)),
+            Err(_) => Err("Unable to write file"),
+        }
+    }
+
+    fn finish(self) -> Result<(), &'static str> {
+        let result = match self {
+            Output::Stdout(mut stdout) => stdout.flush(),
+            Output::File(file) => file.commit(),
+        };
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(_) => Err("Unable to write file"),
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Output::Stdout(stdout) => stdout.write(buf),
+            Output::File(file) => file.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Output::Stdout(stdout) => stdout.flush(),
+            Output::File(file) => file.flush(),
+        }
+    }
 }
 
 fn encrypt_file(config: &Config, options: &EncryptOptions) -> Result<(), &'static str> {
     let encryption = Encryption { config };
-    let raw_contents = match std::fs::read_to_string(&options.from) {
-        Ok(c) => c,
-        Err(_) => return Err("Unable to read file"),
-    };
+    let key = options.key.as_deref().unwrap_or("*");
+    let mut input = open_input(&options.from)?;
+    let mut output = Output::create(&options.to, false)?;
 
-    let encrypted_contents = encryption.encrypt(&options.key, &raw_contents)?;
-    std::fs::write(&options.to, &encrypted_contents).unwrap();
-    Ok(())
+    if options.stream {
+        encrypt_stream(&encryption, key, &mut input, &mut output)?;
+    } else {
+        let mut raw_contents: Vec<u8> = Vec::new();
+        if input.read_to_end(&mut raw_contents).is_err() {
+            return Err("Unable to read file");
+        }
+
+        let encrypted_contents = encrypt_contents(&encryption, key, &raw_contents)?;
+        if output.write_all(encrypted_contents.as_bytes()).is_err() {
+            return Err("Unable to write encrypted file");
+        }
+    }
+
+    output.finish()
 }
 
 fn decrypt_file(config: &Config, options: &DecryptOptions) -> Result<(), &'static str> {
     let encryption = Encryption { config };
-    let encrypted_contents = match std::fs::read_to_string(&options.from) {
-        Ok(c) => c,
-        Err(_) => return Err("Unable to read encrypted file"),
-    };
+    let key = options.key.as_deref().unwrap_or("*");
+    let mut reader = BufReader::new(open_input(&options.from)?);
+
+    // Streamed files are detected by their first line, other files are read in full:
+    let mut first_line = String::new();
+    if reader.read_line(&mut first_line).is_err() {
+        return Err("Unable to read encrypted file");
+    }
+
+    let mut output = Output::create(&options.to, true)?;
+
+    if first_line.starts_with(STREAM_PREFIX) {
+        decrypt_stream(&encryption, key, &first_line, &mut reader, &mut output)?;
+    } else {
+        let mut encrypted_contents = first_line;
+        if reader.read_to_string(&mut encrypted_contents).is_err() {
+            return Err("Unable to read encrypted file");
+        }
+
+        let raw_contents = decrypt_contents(&encryption, key, &encrypted_contents)?;
+        if output.write_all(&raw_contents).is_err() {
+            return Err("Unable to write decrypted file");
+        }
+    }
+
+    output.finish()
+}
 
-    let raw_contents = encryption.decrypt(&options.key, &encrypted_contents)?;
-    std::fs::write(&options.to, &raw_contents).unwrap();
+/// Adds a file to the config file, which is encrypted with the key for its name. Files that
+/// did not change keep their existing encrypted contents.
+fn add_file(config_path: &Path, options: &AddOptions) -> Result<(), &'static str> {
+    let lock = SecretsLock::acquire(config_path)?;
+    let config = Config::get(config_path);
+    let encryption = Encryption { config: &config };
+    check_file_name(&config, &options.name)?;
+
+    let mut raw_contents: Vec<u8> = Vec::new();
+    if open_input(&options.from)?
+        .read_to_end(&mut raw_contents)
+        .is_err()
+    {
+        return Err("Unable to read file");
+    }
+
+    let unchanged = config.files.get(&options.name).filter(|existing| {
+        decrypt_contents(&encryption, &options.name, existing).ok() == Some(raw_contents.clone())
+    });
+    if unchanged.is_some() {
+        println!("The file {} is unchanged.", options.name);
+        return Ok(());
+    }
+
+    let mut new_config = config.clone();
+    new_config.files.insert(
+        options.name.to_string(),
+        encrypt_contents(&encryption, &options.name, &raw_contents)?,
+    );
+    lock.write_config(&config, &new_config)?;
+
+    println!("Added the file {} to {:?}", options.name, config_path);
     Ok(())
 }
 
+fn extract_file(config: &Config, options: &ExtractOptions) -> Result<(), &'static str> {
+    let encryption = Encryption { config };
+    let encrypted_contents = match config.files.get(&options.name) {
+        Some(val) => val,
+        None => return Err("The provided file does not exist in the configuration file."),
+    };
+
+    let raw_contents = decrypt_contents(&encryption, &options.name, encrypted_contents)?;
+    let mut output = Output::create(&options.to, true)?;
+    if output.write_all(&raw_contents).is_err() {
+        return Err("Unable to write decrypted file");
+    }
+
+    output.finish()
+}
+
+impl File {
+    /// Uses the configuration file and the encryption key of the project file, when they
+    /// weren't passed.
+    pub fn apply_project(&mut self, project: &Project) {
+        if self.config.is_none() {
+            self.config = project.file();
+        }
+
+        let key = match &mut self.cmd {
+            FileMode::Encrypt(options) => &mut options.key,
+            FileMode::Decrypt(options) => &mut options.key,
+            FileMode::EncryptDir(options) => &mut options.key,
+            _ => return,
+        };
+        if key.is_none() {
+            *key = project.keys.default.clone();
+        }
+    }
+}
+
 pub fn file(cmd: &File) -> Result<(), &'static str> {
-    if !Config::exists(&cmd.config) {
+    let config_path = config_file(&cmd.config)?;
+    if !Config::exists(config_path) {
         return Err("The provided configuration file does not exist");
     }
 
-    let config = Config::get(&cmd.config);
+    let config = Config::get(config_path);
 
     match &cmd.cmd {
         FileMode::Encrypt(options) => encrypt_file(&config, options),
         FileMode::Decrypt(options) => decrypt_file(&config, options),
+        FileMode::Add(options) => add_file(config_path, options),
+        FileMode::Extract(options) => extract_file(&config, options),
+        FileMode::EncryptDir(options) => encrypt_dir(config_path, options),
+        FileMode::DecryptDir(options) => decrypt_dir(config_path, options),
+        FileMode::Sync(options) => sync(config_path, options),
     }
 }
 
 #[cfg(test)]
 mod tests {
-    // TODO: Write tests
+    use super::*;
+    use std::env;
+
+    fn get_test_path(file_name: &str) -> PathBuf {
+        let mut path = env::current_dir().unwrap();
+        path.push("test");
+        path.push(file_name);
+        path
+    }
+
+    #[test]
+    fn test_encrypt_contents() -> Result<(), &'static str> {
+        let config = Config::get(&get_test_path("secrets.yml"));
+        let encryption = Encryption { config: &config };
+
+        let text = encrypt_contents(&encryption, "*", b"plain text")?;
+        assert!(text.starts_with(TEXT_PREFIX));
+        assert_eq!(decrypt_contents(&encryption, "*", &text)?, b"plain text");
+
+        let binary_contents: Vec<u8> = vec![0x1f, 0x8b, 0x08, 0x00, 0xff];
+        let binary = encrypt_contents(&encryption, "*", &binary_contents)?;
+        assert!(binary.starts_with(BINARY_PREFIX));
+        assert_eq!(
+            decrypt_contents(&encryption, "*", &binary)?,
+            binary_contents
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_encrypt_decrypt_file() -> Result<(), &'static str> {
+        let config = Config::get(&get_test_path("secrets.yml"));
+        let directory = tempfile::tempdir().unwrap();
+        let encrypted_path = directory.path().join("encrypted");
+        let decrypted_path = directory.path().join("decrypted");
+
+        for stream in [false, true].iter() {
+            encrypt_file(
+                &config,
+                &EncryptOptions {
+                    key: Some("*".to_string()),
+                    stream: *stream,
+                    from: get_test_path("decrypted.txt"),
+                    to: encrypted_path.clone(),
+                },
+            )?;
+            decrypt_file(
+                &config,
+                &DecryptOptions {
+                    key: Some("*".to_string()),
+                    from: encrypted_path.clone(),
+                    to: decrypted_path.clone(),
+                },
+            )?;
+
+            assert_eq!(
+                std::fs::read(&decrypted_path).unwrap(),
+                std::fs::read(get_test_path("decrypted.txt")).unwrap()
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_extract_file() -> Result<(), &'static str> {
+        let directory = tempfile::tempdir().unwrap();
+        let config_path = directory.path().join("secrets.yml");
+        let extracted_path = directory.path().join("extracted");
+        std::fs::copy(get_test_path("secrets.yml"), &config_path).unwrap();
+
+        let options = AddOptions {
+            name: "TLS_KEY".to_string(),
+            from: get_test_path("decrypted.txt"),
+        };
+        add_file(&config_path, &options)?;
+        let encrypted = Config::get(&config_path).files["TLS_KEY"].clone();
+
+        // Adding the same contents again keeps the encrypted contents:
+        add_file(&config_path, &options)?;
+        let config = Config::get(&config_path);
+        assert_eq!(config.files["TLS_KEY"], encrypted);
+
+        extract_file(
+            &config,
+            &ExtractOptions {
+                name: "TLS_KEY".to_string(),
+                to: extracted_path.clone(),
+            },
+        )?;
+        assert_eq!(
+            std::fs::read(&extracted_path).unwrap(),
+            std::fs::read(get_test_path("decrypted.txt")).unwrap()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_file_name() {
+        let directory = tempfile::tempdir().unwrap();
+        let config_path = directory.path().join("secrets.yml");
+        std::fs::copy(get_test_path("secrets.yml"), &config_path).unwrap();
+
+        let add = |name: &str| {
+            add_file(
+                &config_path,
+                &AddOptions {
+                    name: name.to_string(),
+                    from: get_test_path("decrypted.txt"),
+                },
+            )
+        };
+        assert_eq!(
+            add("../TLS_KEY"),
+            Err("The names of files can't contain path separators.")
+        );
+        assert_eq!(
+            add("TEST_KEY"),
+            Err("Files can't have the same name as a value in the configuration.")
+        );
+        assert!(Config::get(&config_path).files.is_empty());
+    }
+
+    #[test]
+    fn test_decrypt_unprefixed_contents() -> Result<(), &'static str> {
+        let config = Config::get(&get_test_path("secrets.yml"));
+        let encryption = Encryption { config: &config };
+
+        let encrypted = std::fs::read_to_string(get_test_path("encrypted.txt")).unwrap();
+        let decrypted = std::fs::read(get_test_path("decrypted.txt")).unwrap();
+        assert_eq!(decrypt_contents(&encryption, "*", &encrypted)?, decrypted);
+        Ok(())
+    }
 }
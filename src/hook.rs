@@ -0,0 +1,303 @@
+use crate::export::{quote, ShellKind};
+use crate::project::Project;
+use colored::Colorize;
+use sodiumoxide::crypto::hash::sha256;
+use std::env;
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+
+/// The name of the secrets file that the hook looks for.
+const SECRETS_FILE: &str = "secrets.yml";
+
+#[derive(Debug, StructOpt)]
+pub struct Hook {
+    /// The shell to print the hook for
+    #[structopt(possible_values = &["bash", "zsh", "fish"])]
+    shell: ShellKind,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct Allow {
+    /// Path to the scoob configuration file, which defaults to the nearest `secrets.yml`
+    #[structopt(parse(from_os_str))]
+    file: Option<PathBuf>,
+}
+
+/// Finds the nearest secrets file, in the directory or any of its parents. A directory with
+/// a project file that sets the secrets file uses that file instead. The path is canonical,
+/// as files are allowed by the hash of their canonical path.
+pub fn find_secrets_file(directory: &Path) -> Result<Option<PathBuf>, &'static str> {
+    for ancestor in directory.ancestors() {
+        if let Some(file) = Project::load(ancestor)?.and_then(|project| project.file()) {
+            return Ok(std::fs::canonicalize(file)
+                .ok()
+                .filter(|file| file.is_file()));
+        }
+
+        let file = ancestor.join(SECRETS_FILE);
+        if file.is_file() {
+            return Ok(std::fs::canonicalize(file).ok());
+        }
+    }
+
+    Ok(None)
+}
+
+/// Returns the file that holds the hashes of the allowed secrets files.
+pub fn allow_list_path() -> Result<PathBuf, &'static str> {
+    let data_dir = if cfg!(windows) {
+        env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else {
+        match env::var_os("XDG_DATA_HOME") {
+            Some(val) if !val.is_empty() => Some(PathBuf::from(val)),
+            _ => env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")),
+        }
+    };
+
+    match data_dir {
+        Some(val) => Ok(val.join("scoob").join("allow")),
+        None => Err("Unable to find a directory to store the allowed secrets files in."),
+    }
+}
+
+/// Hashes the path and the contents of a secrets file, so that a file has to be allowed again
+/// after it changed, or after it was moved.
+pub fn file_hash(path: &Path, contents: &str) -> String {
+    let mut data = path.to_string_lossy().as_bytes().to_vec();
+    data.push(0);
+    data.extend_from_slice(contents.as_bytes());
+
+    sha256::hash(&data)
+        .0
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Reads the allow list, which has a line with the hash and the path of each allowed file.
+fn read_allow_list(allow_list: &Path) -> Result<Vec<(String, String)>, &'static str> {
+    let contents = match std::fs::read_to_string(allow_list) {
+        Ok(val) => val,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(_) => return Err("Unable to read the allowed secrets files."),
+    };
+
+    Ok(contents
+        .lines()
+        .filter_map(|line| line.split_once(' '))
+        .map(|(hash, path)| (hash.to_string(), path.to_string()))
+        .collect())
+}
+
+fn write_allow_list(allow_list: &Path, entries: &[(String, String)]) -> Result<(), &'static str> {
+    let contents: String = entries
+        .iter()
+        .map(|(hash, path)| format!("{} {}\n", hash, path))
+        .collect();
+
+    let result = match allow_list.parent() {
+        Some(parent) => std::fs::create_dir_all(parent),
+        None => Ok(()),
+    }
+    .and_then(|_| crate::write::write(allow_list, contents.as_bytes()));
+
+    match result {
+        Ok(_) => Ok(()),
+        Err(_) => Err("Unable to write the allowed secrets files."),
+    }
+}
+
+pub fn is_allowed(allow_list: &Path, hash: &str) -> Result<bool, &'static str> {
+    Ok(read_allow_list(allow_list)?
+        .iter()
+        .any(|(allowed_hash, _)| allowed_hash == hash))
+}
+
+/// Allows the file, replacing the hashes of earlier versions of it.
+pub fn allow(allow_list: &Path, path: &Path, hash: &str) -> Result<(), &'static str> {
+    let mut entries = deny_entries(allow_list, path)?;
+    entries.push((hash.to_string(), path.to_string_lossy().to_string()));
+    write_allow_list(allow_list, &entries)
+}
+
+/// Returns the allow list without any version of the file.
+fn deny_entries(allow_list: &Path, path: &Path) -> Result<Vec<(String, String)>, &'static str> {
+    let path = path.to_string_lossy();
+    Ok(read_allow_list(allow_list)?
+        .into_iter()
+        .filter(|(_, allowed_path)| *allowed_path != path)
+        .collect())
+}
+
+pub fn deny(allow_list: &Path, path: &Path) -> Result<(), &'static str> {
+    write_allow_list(allow_list, &deny_entries(allow_list, path)?)
+}
+
+/// Resolves the file to allow or deny to an absolute path, as the hook looks files up by
+/// their absolute paths.
+fn resolve_file(cmd: &Allow) -> Result<PathBuf, &'static str> {
+    let current_dir = match env::current_dir() {
+        Ok(val) => val,
+        Err(_) => return Err("Unable to read the current directory."),
+    };
+
+    let file = match &cmd.file {
+        Some(file) => current_dir.join(file),
+        None => match find_secrets_file(&current_dir)? {
+            Some(val) => val,
+            None => return Err("No secrets file was found in this directory or its parents."),
+        },
+    };
+
+    match std::fs::canonicalize(&file) {
+        Ok(val) => Ok(val),
+        Err(_) => Err("The provided configuration file does not exist"),
+    }
+}
+
+pub fn allow_file(cmd: &Allow) -> Result<(), &'static str> {
+    let file = resolve_file(cmd)?;
+    let contents = match std::fs::read_to_string(&file) {
+        Ok(val) => val,
+        Err(_) => return Err("Unable to read the scoob configuration file."),
+    };
+
+    allow(&allow_list_path()?, &file, &file_hash(&file, &contents))?;
+    println!("{}", format!("Allowed {}", file.display()).green());
+    Ok(())
+}
+
+pub fn deny_file(cmd: &Allow) -> Result<(), &'static str> {
+    let file = resolve_file(cmd)?;
+    deny(&allow_list_path()?, &file)?;
+    println!("{}", format!("Denied {}", file.display()).green());
+    Ok(())
+}
+
+/// The hook runs `scoob export` before every prompt, and evaluates what it prints.
+fn hook_script(shell: ShellKind, scoob: &str) -> String {
+    let scoob = quote(shell, scoob);
+
+    match shell {
+        ShellKind::Bash => format!(
+            r#"_scoob_hook() {{
+  local previous_exit_status=$?
+  eval "$({} export bash)"
+  return $previous_exit_status
+}}
+if [[ ";${{PROMPT_COMMAND[*]:-}};" != *";_scoob_hook;"* ]]; then
+  PROMPT_COMMAND="_scoob_hook${{PROMPT_COMMAND:+;$PROMPT_COMMAND}}"
+fi
+"#,
+            scoob
+        ),
+        ShellKind::Zsh => format!(
+            r#"_scoob_hook() {{
+  eval "$({} export zsh)"
+}}
+typeset -ag precmd_functions
+if (( ! ${{precmd_functions[(I)_scoob_hook]}} )); then
+  precmd_functions=(_scoob_hook $precmd_functions)
+fi
+typeset -ag chpwd_functions
+if (( ! ${{chpwd_functions[(I)_scoob_hook]}} )); then
+  chpwd_functions=(_scoob_hook $chpwd_functions)
+fi
+"#,
+            scoob
+        ),
+        ShellKind::Fish => format!(
+            r#"function __scoob_hook --on-event fish_prompt --on-variable PWD
+    {} export fish | source
+end
+"#,
+            scoob
+        ),
+    }
+}
+
+pub fn hook(cmd: &Hook) -> Result<(), &'static str> {
+    let scoob = match env::current_exe() {
+        Ok(val) => val.to_string_lossy().to_string(),
+        Err(_) => "scoob".to_string(),
+    };
+
+    print!("{}", hook_script(cmd.shell, &scoob));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_secrets_file() -> Result<(), &'static str> {
+        let directory = tempfile::tempdir().unwrap();
+        let nested = directory.path().join("app").join("src");
+        std::fs::create_dir_all(&nested).unwrap();
+        assert_eq!(find_secrets_file(&nested)?, None);
+
+        let secrets = directory.path().join("app").join("secrets.yml");
+        std::fs::write(&secrets, "").unwrap();
+        let secrets = std::fs::canonicalize(secrets).unwrap();
+        assert_eq!(find_secrets_file(&nested)?, Some(secrets));
+        assert_eq!(find_secrets_file(directory.path())?, None);
+
+        // Project files can point at another secrets file:
+        let project_secrets = nested.join("config").join("dev.yml");
+        std::fs::create_dir_all(project_secrets.parent().unwrap()).unwrap();
+        std::fs::write(&project_secrets, "dev").unwrap();
+        let project_secrets = std::fs::canonicalize(project_secrets).unwrap();
+        std::fs::write(nested.join(".scoob.toml"), "file = \"config/dev.yml\"").unwrap();
+        assert_eq!(find_secrets_file(&nested)?, Some(project_secrets.clone()));
+
+        // Paths in project files are canonical, so that they match the paths that were allowed:
+        std::fs::write(nested.join(".scoob.toml"), "file = \"./config/dev.yml\"").unwrap();
+        let found = find_secrets_file(&nested)?.unwrap();
+        assert_eq!(found, project_secrets);
+
+        let allow_list = directory.path().join("allow");
+        let allowed = resolve_file(&Allow {
+            file: Some(nested.join("./config/dev.yml")),
+        })?;
+        allow(&allow_list, &allowed, &file_hash(&allowed, "dev"))?;
+        assert!(is_allowed(&allow_list, &file_hash(&found, "dev"))?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_allow_deny() -> Result<(), &'static str> {
+        let directory = tempfile::tempdir().unwrap();
+        let allow_list = directory.path().join("scoob").join("allow");
+        let path = Path::new("/app/secrets.yml");
+
+        let first_hash = file_hash(path, "first");
+        let second_hash = file_hash(path, "second");
+        assert_ne!(first_hash, second_hash);
+        assert_ne!(
+            first_hash,
+            file_hash(Path::new("/other/secrets.yml"), "first")
+        );
+        assert!(!is_allowed(&allow_list, &first_hash)?);
+
+        allow(&allow_list, path, &first_hash)?;
+        assert!(is_allowed(&allow_list, &first_hash)?);
+
+        // Allowing a changed file replaces the earlier version:
+        allow(&allow_list, path, &second_hash)?;
+        assert!(!is_allowed(&allow_list, &first_hash)?);
+        assert!(is_allowed(&allow_list, &second_hash)?);
+
+        deny(&allow_list, path)?;
+        assert!(!is_allowed(&allow_list, &second_hash)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_hook_script() {
+        let script = hook_script(ShellKind::Bash, "/usr/local/bin/scoob");
+        assert!(script.contains("eval \"$('/usr/local/bin/scoob' export bash)\""));
+        assert!(hook_script(ShellKind::Zsh, "scoob").contains("chpwd_functions"));
+        assert!(hook_script(ShellKind::Fish, "scoob").contains("'scoob' export fish | source"));
+    }
+}
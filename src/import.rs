@@ -0,0 +1,269 @@
+use crate::config::Config;
+use crate::diff::{print_changes, print_encrypted_changes, Change};
+use crate::encryption::Encryption;
+use crate::lock::SecretsLock;
+use crate::project::{config_file, Project};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+pub struct Import {
+    /// Print the keys that would be added, overwritten or removed, without writing the secrets file
+    #[structopt(long)]
+    dry_run: bool,
+    /// Prefix that is added to the name of every imported key
+    #[structopt(short, long, default_value = "")]
+    prefix: String,
+    /// Remove existing secrets that are not in the imported file, instead of keeping them.
+    /// With `--prefix`, only existing secrets with the prefix are removed
+    #[structopt(long)]
+    replace: bool,
+    /// The dotenv, JSON or YAML file to import secrets from
+    #[structopt(long, parse(from_os_str))]
+    from: PathBuf,
+    /// Path to the scoob configuration file, which defaults to the file set in the project file
+    #[structopt(parse(from_os_str))]
+    file: Option<PathBuf>,
+}
+
+impl Import {
+    /// Uses the configuration file of the project file, when it wasn't passed.
+    pub fn apply_project(&mut self, project: &Project) {
+        if self.file.is_none() {
+            self.file = project.file();
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Format {
+    Dotenv,
+    Json,
+    Yaml,
+}
+
+fn detect_format(path: &Path) -> Result<Format, &'static str> {
+    let file_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("");
+
+    if file_name.starts_with(".env") {
+        return Ok(Format::Dotenv);
+    }
+
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("env") => Ok(Format::Dotenv),
+        Some("json") => Ok(Format::Json),
+        Some("yml") | Some("yaml") => Ok(Format::Yaml),
+        _ => Err("Unable to determine the format of the imported file. Use a .env, .json, .yml or .yaml file."),
+    }
+}
+
+fn json_to_string(value: &serde_json::Value) -> Result<String, &'static str> {
+    match value {
+        serde_json::Value::String(val) => Ok(val.to_string()),
+        serde_json::Value::Number(val) => Ok(val.to_string()),
+        serde_json::Value::Bool(val) => Ok(val.to_string()),
+        _ => Err("Only strings, numbers and booleans can be imported."),
+    }
+}
+
+fn yaml_to_string(value: &serde_yaml::Value) -> Result<String, &'static str> {
+    match value {
+        serde_yaml::Value::String(val) => Ok(val.to_string()),
+        serde_yaml::Value::Number(val) => Ok(val.to_string()),
+        serde_yaml::Value::Bool(val) => Ok(val.to_string()),
+        _ => Err("Only strings, numbers and booleans can be imported."),
+    }
+}
+
+fn read_values(path: &Path) -> Result<HashMap<String, String>, &'static str> {
+    let format = detect_format(path)?;
+    let mut values: HashMap<String, String> = HashMap::new();
+
+    if format == Format::Dotenv {
+        let file = match std::fs::File::open(path) {
+            Ok(val) => val,
+            Err(_) => return Err("Unable to read the imported file."),
+        };
+
+        for entry in dotenvy::Iter::new(file) {
+            match entry {
+                Ok((key, value)) => values.insert(key, value),
+                Err(_) => return Err("The imported file is not a valid dotenv file."),
+            };
+        }
+
+        return Ok(values);
+    }
+
+    let contents = match std::fs::read_to_string(path) {
+        Ok(val) => val,
+        Err(_) => return Err("Unable to read the imported file."),
+    };
+
+    if format == Format::Json {
+        let object: serde_json::Map<String, serde_json::Value> =
+            match serde_json::from_str(&contents) {
+                Ok(val) => val,
+                Err(_) => return Err("The imported file is not a valid JSON object."),
+            };
+
+        for (key, value) in object.iter() {
+            values.insert(key.to_string(), json_to_string(value)?);
+        }
+    } else {
+        let mapping: serde_yaml::Mapping = match serde_yaml::from_str(&contents) {
+            Ok(val) => val,
+            Err(_) => return Err("The imported file is not a valid YAML mapping."),
+        };
+
+        for (key, value) in mapping.iter() {
+            let key = match key.as_str() {
+                Some(val) => val,
+                None => return Err("Only string keys can be imported."),
+            };
+            values.insert(key.to_string(), yaml_to_string(value)?);
+        }
+    }
+
+    Ok(values)
+}
+
+/// Builds the configuration to encrypt, where existing values are left as placeholders so
+/// that they keep their ciphertext. Replacing only removes the existing keys with the prefix,
+/// which are the keys that the imported file is the source of.
+fn import_values(
+    config: &Config,
+    values: &HashMap<String, String>,
+    prefix: &str,
+    replace: bool,
+) -> (Config, Vec<Change>) {
+    let mut new_config = config.with_placeholders();
+    let mut changes: Vec<Change> = Vec::new();
+
+    if replace {
+        for key in config.configuration.keys() {
+            let imported = match key.strip_prefix(prefix) {
+                Some(name) => values.contains_key(name),
+                None => true,
+            };
+            if !imported {
+                new_config.configuration.remove(key);
+                changes.push(Change::Removed(key.to_string()));
+            }
+        }
+    }
+
+    for (key, value) in values.iter() {
+        let key = format!("{}{}", prefix, key);
+
+        if config.configuration.contains_key(&key) {
+            changes.push(Change::Changed(key.to_string()));
+        } else {
+            changes.push(Change::Added(key.to_string()));
+        }
+
+        new_config.configuration.insert(key, value.to_string());
+    }
+
+    changes.sort_by(|a, b| a.key().cmp(b.key()));
+    (new_config, changes)
+}
+
+pub fn import(cmd: &Import) -> Result<(), &'static str> {
+    let file = config_file(&cmd.file)?;
+    if !Config::exists(file) {
+        return Err("The provided configuration file does not exist");
+    }
+
+    let values = read_values(&cmd.from)?;
+    let lock = SecretsLock::acquire(file)?;
+    let config = Config::get(file);
+
+    let (new_config, changes) = import_values(&config, &values, &cmd.prefix, cmd.replace);
+
+    if cmd.dry_run {
+        if changes.is_empty() {
+            println!("No secrets would be imported.");
+        } else {
+            println!("Keys that would be added (+), overwritten (~) or removed (-):");
+            print_changes(&changes, None, None);
+        }
+        return Ok(());
+    }
+
+    let encrypted_config = Encryption { config: &config }.encrypt_configuration(&new_config)?;
+
+    let written_config = lock.write_config(&config, &encrypted_config)?;
+
+    println!("Wrote updated scoob configuration file at {:?}", file);
+
+    print_encrypted_changes(&config.configuration, &written_config.configuration);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn get_test_path(file_name: &str) -> PathBuf {
+        let mut path = env::current_dir().unwrap();
+        path.push("test");
+        path.push(file_name);
+        path
+    }
+
+    #[test]
+    fn test_detect_format() {
+        assert_eq!(detect_format(Path::new(".env")), Ok(Format::Dotenv));
+        assert_eq!(detect_format(Path::new("./.env.local")), Ok(Format::Dotenv));
+        assert_eq!(detect_format(Path::new("prod.env")), Ok(Format::Dotenv));
+        assert_eq!(detect_format(Path::new("config.json")), Ok(Format::Json));
+        assert_eq!(detect_format(Path::new("plain.yaml")), Ok(Format::Yaml));
+        assert!(detect_format(Path::new("secrets.txt")).is_err());
+    }
+
+    #[test]
+    fn test_read_values() -> Result<(), &'static str> {
+        for file_name in ["import.env", "import.json", "import.yml"].iter() {
+            let values = read_values(&get_test_path(file_name))?;
+            assert_eq!(values["DATABASE_URL"], "postgres://localhost/scoob");
+            assert_eq!(values["PORT"], "8080");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_values() {
+        let config = Config::get(&get_test_path("secrets.yml"));
+        let mut values: HashMap<String, String> = HashMap::new();
+        values.insert("KEY".to_string(), "value".to_string());
+
+        let (new_config, changes) = import_values(&config, &values, "TEST_", false);
+        assert_eq!(new_config.configuration["TEST_KEY"], "value");
+        assert_eq!(new_config.configuration["EXAMPLE_KEY"], "<encrypted>");
+        assert_eq!(changes, vec![Change::Changed("TEST_KEY".to_string())]);
+
+        let (new_config, changes) = import_values(&config, &values, "", true);
+        assert_eq!(new_config.configuration.len(), 1);
+        assert_eq!(
+            changes,
+            vec![
+                Change::Removed("EXAMPLE_KEY".to_string()),
+                Change::Added("KEY".to_string()),
+                Change::Removed("TEST_KEY".to_string()),
+            ]
+        );
+
+        // Replacing with a prefix keeps the keys without the prefix:
+        let (new_config, changes) = import_values(&config, &values, "OTHER_", true);
+        assert_eq!(new_config.configuration["TEST_KEY"], "<encrypted>");
+        assert_eq!(new_config.configuration["EXAMPLE_KEY"], "<encrypted>");
+        assert_eq!(changes, vec![Change::Added("OTHER_KEY".to_string())]);
+    }
+}
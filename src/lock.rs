@@ -0,0 +1,237 @@
+use crate::config::Config;
+use crate::editor::confirm;
+use crate::write::write;
+use colored::Colorize;
+use std::fs::{File, OpenOptions};
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+#[cfg(unix)]
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+#[cfg(unix)]
+fn try_lock(file: &File) -> bool {
+    unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) == 0 }
+}
+
+#[cfg(unix)]
+fn lock(file: &File) -> bool {
+    unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) == 0 }
+}
+
+/// Checks that the path still points to the locked file. Secrets files are replaced when they
+/// are written, so a lock on the previous file no longer protects anything.
+#[cfg(unix)]
+fn is_current(file: &File, path: &Path) -> bool {
+    match (file.metadata(), std::fs::metadata(path)) {
+        (Ok(locked), Ok(current)) => locked.dev() == current.dev() && locked.ino() == current.ino(),
+        _ => false,
+    }
+}
+
+/// The file that is locked while a configuration file that doesn't exist yet is created, such
+/// as `.secrets.yml.lock` for `secrets.yml`.
+fn lock_file_path(path: &Path) -> PathBuf {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{}.lock", file_name))
+}
+
+/// An advisory lock on a scoob configuration file, which is held while it is being edited so
+/// that concurrent scoob sessions don't overwrite each other's changes. Files that don't exist
+/// yet are locked through a lock file next to them, which is removed again when the lock is
+/// dropped. The lock is released when it is dropped.
+pub struct SecretsLock {
+    path: PathBuf,
+    // Held so that the lock is released when it is dropped:
+    _file: Option<File>,
+    lock_file: Option<PathBuf>,
+    contents: Option<String>,
+}
+
+impl SecretsLock {
+    pub fn acquire(path: &Path) -> Result<SecretsLock, &'static str> {
+        let (file, lock_file) = SecretsLock::lock_file(path)?;
+
+        Ok(SecretsLock {
+            path: path.to_path_buf(),
+            _file: file,
+            lock_file,
+            contents: std::fs::read_to_string(path).ok(),
+        })
+    }
+
+    #[cfg(unix)]
+    fn lock_file(path: &Path) -> Result<(Option<File>, Option<PathBuf>), &'static str> {
+        let mut waiting = false;
+
+        loop {
+            let (file, locked_path) = match File::open(path) {
+                Ok(val) => (val, path.to_path_buf()),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                    let lock_path = lock_file_path(path);
+                    match OpenOptions::new()
+                        .write(true)
+                        .create(true)
+                        .truncate(false)
+                        .open(&lock_path)
+                    {
+                        Ok(val) => (val, lock_path),
+                        Err(_) => return Err("Unable to lock the scoob configuration file."),
+                    }
+                }
+                Err(_) => return Err("Unable to lock the scoob configuration file."),
+            };
+
+            if !try_lock(&file) {
+                if !waiting {
+                    println!(
+                        "Waiting for another scoob session to finish editing {:?}...",
+                        path
+                    );
+                    waiting = true;
+                }
+
+                if !lock(&file) {
+                    return Err("Unable to lock the scoob configuration file.");
+                }
+            }
+
+            if !is_current(&file, &locked_path) {
+                continue;
+            }
+
+            if locked_path == path {
+                return Ok((Some(file), None));
+            }
+
+            // The file may have been created by the session that held the lock before, in
+            // which case the file itself is locked instead:
+            if !path.exists() {
+                return Ok((Some(file), Some(locked_path)));
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn lock_file(_path: &Path) -> Result<(Option<File>, Option<PathBuf>), &'static str> {
+        Ok((None, None))
+    }
+
+    /// Writes the configuration file, and returns the configuration that was written. If the
+    /// file was changed since the lock was acquired, which can happen when it is edited without
+    /// scoob, the user is offered to merge their changes into it.
+    pub fn write_config(&self, base: &Config, config: &Config) -> Result<Config, &'static str> {
+        let current_contents = std::fs::read_to_string(&self.path).ok();
+
+        let config = if current_contents != self.contents {
+            println!(
+                "{}",
+                "The secrets file was changed while you were editing it."
+                    .yellow()
+                    .bold()
+            );
+
+            let current_config = match &current_contents {
+                Some(contents) => Config::from_yaml(contents)?,
+                None => Config::empty(),
+            };
+
+            if confirm("Do you want to merge your changes into it?") {
+                Config::merge(base, config, &current_config)?
+            } else if confirm("Do you want to overwrite it with your changes?") {
+                config.clone()
+            } else {
+                return Err("The secrets file was not written, and your changes were discarded.");
+            }
+        } else {
+            config.clone()
+        };
+
+        match write(
+            &self.path,
+            serde_yaml::to_string(&config).unwrap().as_bytes(),
+        ) {
+            Ok(_) => Ok(config),
+            Err(_) => Err("Unable to write the scoob configuration file."),
+        }
+    }
+}
+
+impl Drop for SecretsLock {
+    fn drop(&mut self) {
+        // The lock file is removed while it is still locked, and sessions that were waiting
+        // for it notice that it was removed once they get the lock:
+        if let Some(lock_file) = &self.lock_file {
+            std::fs::remove_file(lock_file).ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_config() -> Result<(), &'static str> {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("secrets.yml");
+        let config = Config::default();
+
+        let lock = SecretsLock::acquire(&path)?;
+        lock.write_config(&config, &config)?;
+        drop(lock);
+
+        let lock = SecretsLock::acquire(&path)?;
+        lock.write_config(&config, &config)?;
+        assert_eq!(
+            Config::get(&path).configuration["EXAMPLE_KEY"],
+            config.configuration["EXAMPLE_KEY"]
+        );
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_lock() -> std::io::Result<()> {
+        let directory = tempfile::tempdir()?;
+        let path = directory.path().join("secrets.yml");
+        std::fs::write(&path, "")?;
+
+        let lock = SecretsLock::acquire(&path).unwrap();
+        assert!(!try_lock(&File::open(&path)?));
+        drop(lock);
+        assert!(try_lock(&File::open(&path)?));
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_lock_new_file() -> Result<(), &'static str> {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("secrets.yml");
+        let lock_path = directory.path().join(".secrets.yml.lock");
+        let config = Config::default();
+
+        // Files that don't exist yet are locked through the lock file:
+        let lock = SecretsLock::acquire(&path)?;
+        assert!(!try_lock(&File::open(&lock_path).unwrap()));
+
+        // A second session waits for the file to be created, and then sees the created file:
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let waiting_path = path.clone();
+        let handle = std::thread::spawn(move || {
+            let lock = SecretsLock::acquire(&waiting_path);
+            sender.send(()).unwrap();
+            lock.map(|lock| lock.contents.clone())
+        });
+        assert!(receiver
+            .recv_timeout(std::time::Duration::from_millis(200))
+            .is_err());
+
+        lock.write_config(&Config::empty(), &config)?;
+        drop(lock);
+        assert!(!lock_path.exists());
+        assert_eq!(handle.join().unwrap()?, std::fs::read_to_string(&path).ok());
+        Ok(())
+    }
+}
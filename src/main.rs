@@ -1,10 +1,26 @@
 mod config;
+mod diff;
+mod editor;
 mod encryption;
+mod export;
 mod file;
 mod generate_keys;
+mod hook;
+mod import;
+mod lock;
 mod manage;
+mod manifest;
+mod private;
+mod project;
+mod render;
+mod shell;
 mod start;
+mod stream;
+mod supervise;
+mod template;
+mod write;
 
+use crate::project::Project;
 use colored::Colorize;
 use std::alloc::System;
 use structopt::StructOpt;
@@ -18,9 +34,33 @@ enum Opt {
     /// Manage a scoob configuration file
     Manage(crate::manage::Manage),
 
+    /// Imports secrets from a dotenv, JSON or YAML file into a scoob configuration file
+    Import(crate::import::Import),
+
     /// Runs a command after loading scoob secrets into the environment
     Start(crate::start::Start),
 
+    /// Starts an interactive shell with scoob secrets loaded into the environment
+    Shell(crate::shell::Shell),
+
+    /// Prints the secrets as statements that set environment variables in a shell
+    Export(crate::export::Export),
+
+    /// Prints a shell hook that loads the nearest secrets file when entering a directory
+    Hook(crate::hook::Hook),
+
+    /// Allows the shell hook to load a secrets file
+    Allow(crate::hook::Allow),
+
+    /// Stops the shell hook from loading a secrets file
+    Deny(crate::hook::Allow),
+
+    /// Renders a template with the values of scoob secrets into a file
+    Render(crate::render::Render),
+
+    /// Shows which secrets changed between two scoob configuration files
+    Diff(crate::diff::Diff),
+
     /// Utilities for encrypting files
     File(crate::file::File),
 
@@ -33,14 +73,44 @@ fn main() {
         return println!("{}", String::from("Was not able to initialize Sodium. Verify your installation of Scoob and try again.").red().bold());
     }
 
-    // Load the .env file into the current environment:
-    dotenv::dotenv().ok();
+    let mut cli = Opt::from_args();
+
+    // Only commands that use the project file read it, so that an invalid project file can't
+    // break the others:
+    let project = if uses_project(&cli) {
+        match Project::current() {
+            Ok(val) => val,
+            Err(message) => exit_with_error(message),
+        }
+    } else {
+        Project::default()
+    };
 
-    let cli = Opt::from_args();
+    // Load the .env file into the current environment, unless the project file or starting a
+    // command disables it:
+    let loads_dotenv = project.loads_dotenv()
+        && match &cli {
+            Opt::Start(c) => c.loads_dotenv(),
+            _ => true,
+        };
+    if loads_dotenv {
+        dotenvy::dotenv().ok();
+    }
+
+    if let Err(message) = apply_project(&mut cli, &project) {
+        exit_with_error(message);
+    }
 
     let result = match &cli {
         Opt::GenerateKeys(c) => crate::generate_keys::generate_keys(c),
         Opt::Manage(c) => crate::manage::manage(c),
+        Opt::Diff(c) => crate::diff::diff(c),
+        Opt::Render(c) => crate::render::render(c),
+        Opt::Export(c) => crate::export::export(c),
+        Opt::Hook(c) => crate::hook::hook(c),
+        Opt::Allow(c) => crate::hook::allow_file(c),
+        Opt::Deny(c) => crate::hook::deny_file(c),
+        Opt::Import(c) => crate::import::import(c),
         Opt::File(c) => crate::file::file(c),
         Opt::Start(c) => {
             let start_result = crate::start::start(c);
@@ -50,10 +120,50 @@ fn main() {
                 Err(err) => Err(err),
             }
         }
+        Opt::Shell(c) => match crate::shell::shell(c) {
+            Ok(status) => std::process::exit(status),
+            Err(err) => Err(err),
+        },
     };
 
-    match result {
-        Ok(_) => (),
-        Err(message) => println!("{}", String::from(message).red().bold()),
-    };
+    if let Err(message) = result {
+        exit_with_error(message);
+    }
+}
+
+/// Whether the command uses the settings of the project file. Commands that decrypt secrets
+/// use it for the AWS region that keys are read from.
+fn uses_project(cli: &Opt) -> bool {
+    !matches!(
+        cli,
+        Opt::GenerateKeys(_) | Opt::Hook(_) | Opt::Allow(_) | Opt::Deny(_)
+    )
+}
+
+/// Uses the settings of the project file for the options that weren't passed.
+fn apply_project(cli: &mut Opt, project: &Project) -> Result<(), &'static str> {
+    match cli {
+        Opt::Manage(c) => c.apply_project(project),
+        Opt::File(c) => c.apply_project(project),
+        Opt::Shell(c) => c.apply_project(project),
+        Opt::Import(c) => c.apply_project(project),
+        Opt::Start(c) => c.apply_project(project, &std::env::args_os().collect::<Vec<_>>())?,
+        _ => (),
+    }
+
+    // Key providers read their settings from the environment, where variables that were
+    // already set take precedence over the project file:
+    if let Some(region) = &project.keys.aws_region {
+        if std::env::var_os("SCOOB_AWS_REGION").is_none() {
+            std::env::set_var("SCOOB_AWS_REGION", region);
+        }
+    }
+
+    Ok(())
+}
+
+/// Errors are written to stderr, so that they aren't mixed into output written to stdout.
+fn exit_with_error(message: &str) -> ! {
+    eprintln!("{}", String::from(message).red().bold());
+    std::process::exit(1);
 }
@@ -1,5 +1,11 @@
 use crate::config::Config;
+use crate::diff::print_encrypted_changes;
+use crate::editor::{confirm, edit_secrets};
 use crate::encryption::Encryption;
+use crate::lock::SecretsLock;
+use crate::project::{config_file, Project};
+use colored::Colorize;
+use std::collections::HashMap;
 use std::env;
 use std::path::PathBuf;
 use structopt::StructOpt;
@@ -17,9 +23,82 @@ pub struct Manage {
     /// Enforce creation of the configuration file. Scoob will error if the file already exists
     #[structopt(short, long)]
     create: bool,
-    /// Path to the scoob configuration file
+    /// Show the decrypted value of this key in the editor, given once for each key, such as
+    /// `--reveal DATABASE_URL --reveal API_TOKEN`. Without a key, all values are shown
+    #[structopt(short, long, min_values = 0, number_of_values = 1)]
+    reveal: Option<Vec<String>>,
+    /// Path to the scoob configuration file, which defaults to the file set in the project file
     #[structopt(parse(from_os_str))]
-    file: PathBuf,
+    file: Option<PathBuf>,
+}
+
+impl Manage {
+    /// Uses the configuration file of the project file, when it wasn't passed.
+    pub fn apply_project(&mut self, project: &Project) {
+        if self.file.is_none() {
+            self.file = project.file();
+        }
+    }
+}
+
+/// Replaces the placeholders for the provided keys with their decrypted values, or all
+/// placeholders if no keys are provided, and returns the decrypted values.
+fn reveal(
+    encryption: &Encryption,
+    config: &mut Config,
+    keys: &[String],
+) -> Result<HashMap<String, String>, &'static str> {
+    let keys: Vec<String> = if keys.is_empty() {
+        config.configuration.keys().cloned().collect()
+    } else {
+        keys.to_vec()
+    };
+
+    let mut revealed: HashMap<String, String> = HashMap::new();
+    for key in keys.iter() {
+        let value = match encryption.config.configuration.get(key) {
+            Some(val) => val,
+            None => return Err("A key provided to '--reveal' does not exist in the secrets file."),
+        };
+
+        let decrypted = encryption.decrypt(key, value)?;
+        config
+            .configuration
+            .insert(key.to_string(), decrypted.to_string());
+        revealed.insert(key.to_string(), decrypted);
+    }
+
+    Ok(revealed)
+}
+
+const ERROR_COMMENT: &str =
+    "# The secrets could not be saved. Fix the error below, or remove all lines to abort:";
+
+fn strip_error_comment(contents: &str) -> &str {
+    match contents.strip_prefix(ERROR_COMMENT) {
+        Some(rest) => {
+            let mut rest = rest;
+            while let Some(line_end) = rest.find('\n') {
+                if !rest[..line_end].starts_with('#') && !rest[..line_end].is_empty() {
+                    break;
+                }
+                rest = &rest[line_end + 1..];
+            }
+            rest
+        }
+        None => contents,
+    }
+}
+
+/// Adds the error as a comment to the top of the edited contents, replacing a previous error.
+fn with_error_comment(contents: &str, message: &str) -> String {
+    let mut commented = format!("{}\n", ERROR_COMMENT);
+    for line in message.lines() {
+        commented.push_str(&format!("#   {}\n", line));
+    }
+    commented.push('\n');
+    commented.push_str(strip_error_comment(contents));
+    commented
 }
 
 pub fn manage(cmd: &Manage) -> Result<(), &'static str> {
@@ -37,42 +116,153 @@ pub fn manage(cmd: &Manage) -> Result<(), &'static str> {
         return Err("Both '--edit' and '--create' flags cannot be provided");
     }
 
-    if cmd.create && Config::exists(&cmd.file) {
+    if cmd.create && cmd.reveal.is_some() {
+        return Err("The reveal flag can only be used when editing an existing secrets file.");
+    }
+
+    let file = config_file(&cmd.file)?;
+
+    if cmd.create && Config::exists(file) {
         return Err("The create flag was provided, but the secrets file already exists.");
     }
 
-    if cmd.edit && !Config::exists(&cmd.file) {
+    if cmd.edit && !Config::exists(file) {
         return Err("The edit flag was provided, but the secrets file does not exist.");
     }
 
-    let mode: Mode = if cmd.create || !Config::exists(&cmd.file) {
+    // The file can be created by another session while waiting for the lock, so whether it
+    // exists is checked again once the lock is held:
+    let lock = SecretsLock::acquire(file)?;
+    if cmd.create && Config::exists(file) {
+        return Err("The create flag was provided, but the secrets file already exists.");
+    }
+
+    let mode: Mode = if cmd.create || !Config::exists(file) {
         Mode::Create
     } else {
         Mode::Edit
     };
 
-    let original_config = Config::get(&cmd.file);
+    let original_config = Config::get(file);
     let encryption = Encryption {
         config: &original_config,
     };
 
-    let temp_file_contents = match mode {
+    let mut temp_file_contents = match mode {
         Mode::Create => Config::default(),
         Mode::Edit => original_config.with_placeholders(),
     };
 
-    let contents = edit::edit_with_builder(
-        serde_yaml::to_string(&temp_file_contents).unwrap(),
-        edit::Builder::new().suffix(".yml"),
-    );
+    let revealed = match &cmd.reveal {
+        Some(keys) => reveal(&encryption, &mut temp_file_contents, keys)?,
+        None => HashMap::new(),
+    };
+
+    let mut contents = serde_yaml::to_string(&temp_file_contents).unwrap();
+
+    // Like `kubectl edit`, invalid changes re-open the editor so that they aren't lost:
+    let encrypted_config = loop {
+        let edited = edit_secrets(&contents)?;
 
-    let new_config: Config = serde_yaml::from_str(&contents.unwrap()).unwrap();
+        if strip_error_comment(&edited).trim().is_empty() {
+            return Err("The secrets file was emptied, no changes were made.");
+        }
+
+        let result = serde_yaml::from_str::<Config>(&edited)
+            .map_err(|err| err.to_string())
+            .and_then(|new_config| {
+                encryption
+                    .encrypt_configuration_with(&new_config, &revealed)
+                    .map_err(|err| err.to_string())
+            });
+
+        match result {
+            Ok(val) => break val,
+            Err(message) => {
+                println!("{}", message.red().bold());
+                if !confirm("Do you want to edit the secrets again?") {
+                    return Err("Editing was aborted, no changes were made.");
+                }
+                contents = with_error_comment(&edited, &message);
+            }
+        }
+    };
 
-    let encrypted_config = encryption.encrypt_configuration(&new_config)?;
+    let base_config = match mode {
+        Mode::Create => Config::empty(),
+        Mode::Edit => original_config.clone(),
+    };
+    let written_config = lock.write_config(&base_config, &encrypted_config)?;
 
-    std::fs::write(&cmd.file, serde_yaml::to_string(&encrypted_config).unwrap()).unwrap();
+    println!("Wrote updated scoob configuration file at {:?}", file);
 
-    println!("Wrote updated scoob configuration file at {:?}", cmd.file);
+    print_encrypted_changes(&base_config.configuration, &written_config.configuration);
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn get_secrets_path() -> PathBuf {
+        let mut path = env::current_dir().unwrap();
+        path.push("test");
+        path.push("secrets.yml");
+        path
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let secrets_path = get_secrets_path();
+        let file = secrets_path.to_str().unwrap();
+
+        let options = Manage::from_iter(&["manage", "--reveal", "TEST_KEY", file]);
+        assert_eq!(options.reveal, Some(vec!["TEST_KEY".to_string()]));
+        assert_eq!(options.file, Some(secrets_path.clone()));
+
+        let options = Manage::from_iter(&["manage", "-r", "TEST_KEY", "-r", "EXAMPLE_KEY", file]);
+        assert_eq!(
+            options.reveal,
+            Some(vec!["TEST_KEY".to_string(), "EXAMPLE_KEY".to_string()])
+        );
+        assert_eq!(options.file, Some(secrets_path.clone()));
+
+        // The form in the README:
+        let options = Manage::from_iter(&[
+            "manage",
+            file,
+            "--reveal",
+            "DATABASE_URL",
+            "--reveal",
+            "API_TOKEN",
+        ]);
+        assert_eq!(
+            options.reveal,
+            Some(vec!["DATABASE_URL".to_string(), "API_TOKEN".to_string()])
+        );
+        assert_eq!(options.file, Some(secrets_path.clone()));
+
+        let options = Manage::from_iter(&["manage", file, "--reveal"]);
+        assert_eq!(options.reveal, Some(vec![]));
+        assert_eq!(options.file, Some(secrets_path.clone()));
+
+        // Each --reveal takes a single key:
+        assert!(Manage::from_iter_safe(&["manage", file, "--reveal", "A", "B"]).is_err());
+    }
+
+    #[test]
+    fn test_error_comment() {
+        let contents = "---\nconfiguration: [\n";
+        let commented = with_error_comment(contents, "first error");
+        assert!(commented.starts_with(ERROR_COMMENT));
+        assert!(commented.contains("#   first error"));
+        assert!(commented.ends_with(contents));
+
+        let recommented = with_error_comment(&commented, "second error");
+        assert!(!recommented.contains("first error"));
+        assert!(recommented.contains("#   second error"));
+        assert_eq!(strip_error_comment(&recommented), contents);
+    }
+}
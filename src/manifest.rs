@@ -0,0 +1,340 @@
+use crate::config::{Config, ManifestEntry};
+use crate::encryption::Encryption;
+use crate::file::{decrypt_contents, encrypt_contents};
+use crate::lock::SecretsLock;
+use crate::write::{write, AtomicFile};
+use glob::{MatchOptions, Pattern};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+
+/// Encrypted files are written next to the raw file, with this extension added.
+const ENCRYPTED_EXTENSION: &str = ".enc";
+
+#[derive(Debug, StructOpt)]
+pub struct EncryptDirOptions {
+    /// The encryption key from the config file that will be used, which defaults to the key
+    /// set in the project file, or `*`
+    #[structopt(short, long)]
+    pub key: Option<String>,
+
+    /// Only encrypt files matching these glob patterns, such as `*.yml`
+    #[structopt(short, long, number_of_values = 1)]
+    pattern: Vec<String>,
+
+    /// The directory with the raw, unencrypted files
+    #[structopt(parse(from_os_str))]
+    directory: PathBuf,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct DecryptDirOptions {
+    /// Only decrypt files matching these glob patterns, such as `*.yml`
+    #[structopt(short, long, number_of_values = 1)]
+    pattern: Vec<String>,
+
+    /// The directory to decrypt the files in the manifest in
+    #[structopt(parse(from_os_str))]
+    directory: PathBuf,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct SyncOptions {
+    /// Only encrypt files matching these glob patterns, such as `*.yml`
+    #[structopt(short, long, number_of_values = 1)]
+    pattern: Vec<String>,
+}
+
+/// Paths in the manifest are relative to the directory of the scoob configuration file.
+fn base_directory(config_path: &Path) -> Result<PathBuf, &'static str> {
+    let directory = match config_path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+
+    match directory.canonicalize() {
+        Ok(val) => Ok(val),
+        Err(_) => Err("Unable to read the directory of the scoob configuration file."),
+    }
+}
+
+fn relative_path(base: &Path, path: &Path) -> Result<String, &'static str> {
+    let path = match path.canonicalize() {
+        Ok(val) => val,
+        Err(_) => return Err("Unable to read file"),
+    };
+
+    match path.strip_prefix(base) {
+        Ok(relative) => Ok(relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/")),
+        Err(_) => Err(
+            "Files in the manifest must be inside the directory of the scoob configuration file.",
+        ),
+    }
+}
+
+fn parse_patterns(patterns: &[String]) -> Result<Vec<Pattern>, &'static str> {
+    patterns
+        .iter()
+        .map(|pattern| Pattern::new(pattern).map_err(|_| "The provided glob pattern is not valid."))
+        .collect()
+}
+
+/// Files match when they match any of the patterns, or when there are no patterns.
+fn matches(patterns: &[Pattern], path: &str) -> bool {
+    let options = MatchOptions {
+        require_literal_separator: false,
+        ..MatchOptions::new()
+    };
+
+    patterns.is_empty()
+        || patterns
+            .iter()
+            .any(|pattern| pattern.matches_with(path, options))
+}
+
+fn collect_files(directory: &Path, files: &mut Vec<PathBuf>) -> Result<(), &'static str> {
+    let entries = match std::fs::read_dir(directory) {
+        Ok(val) => val,
+        Err(_) => return Err("Unable to read the provided directory."),
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => collect_files(&path, files)?,
+            Ok(file_type) if file_type.is_file() => files.push(path),
+            _ => (),
+        }
+    }
+
+    Ok(())
+}
+
+/// Encrypts a file in the manifest. Files are only encrypted again when their contents no
+/// longer match the encrypted file, which keeps unchanged encrypted files as they are.
+fn encrypt_entry(
+    encryption: &Encryption,
+    base: &Path,
+    path: &str,
+    entry: &ManifestEntry,
+) -> Result<bool, &'static str> {
+    let raw_contents = match std::fs::read(base.join(path)) {
+        Ok(val) => val,
+        Err(_) => return Err("Unable to read file"),
+    };
+
+    let encrypted_path = base.join(&entry.encrypted);
+    if let Ok(existing) = std::fs::read_to_string(&encrypted_path) {
+        if decrypt_contents(encryption, &entry.key, &existing).ok() == Some(raw_contents.clone()) {
+            return Ok(false);
+        }
+    }
+
+    let encrypted_contents = encrypt_contents(encryption, &entry.key, &raw_contents)?;
+    match write(&encrypted_path, encrypted_contents.as_bytes()) {
+        Ok(_) => Ok(true),
+        Err(_) => Err("Unable to write encrypted file"),
+    }
+}
+
+fn print_result(path: &str, entry: &ManifestEntry, encrypted: bool) {
+    if encrypted {
+        println!("Encrypted {} to {}", path, entry.encrypted);
+    } else {
+        println!("Unchanged {}", path);
+    }
+}
+
+pub fn encrypt_dir(config_path: &Path, options: &EncryptDirOptions) -> Result<(), &'static str> {
+    let lock = SecretsLock::acquire(config_path)?;
+    let config = Config::get(config_path);
+    let encryption = Encryption { config: &config };
+    let base = base_directory(config_path)?;
+    let patterns = parse_patterns(&options.pattern)?;
+    let config_relative_path = relative_path(&base, config_path)?;
+
+    let mut files: Vec<PathBuf> = Vec::new();
+    collect_files(&options.directory, &mut files)?;
+    files.sort();
+
+    let mut new_config = config.clone();
+    for file in files.iter() {
+        let path = relative_path(&base, file)?;
+
+        if path.ends_with(ENCRYPTED_EXTENSION)
+            || path == config_relative_path
+            || !matches(&patterns, &path)
+        {
+            continue;
+        }
+
+        let entry = ManifestEntry {
+            encrypted: format!("{}{}", path, ENCRYPTED_EXTENSION),
+            key: options.key.as_deref().unwrap_or("*").to_string(),
+        };
+        print_result(
+            &path,
+            &entry,
+            encrypt_entry(&encryption, &base, &path, &entry)?,
+        );
+        new_config.manifest.insert(path, entry);
+    }
+
+    lock.write_config(&config, &new_config)?;
+    Ok(())
+}
+
+pub fn decrypt_dir(config_path: &Path, options: &DecryptDirOptions) -> Result<(), &'static str> {
+    let config = Config::get(config_path);
+    let encryption = Encryption { config: &config };
+    let base = base_directory(config_path)?;
+    let patterns = parse_patterns(&options.pattern)?;
+    let directory = relative_path(&base, &options.directory)?;
+
+    let mut paths: Vec<&String> = config.manifest.keys().collect();
+    paths.sort();
+
+    for path in paths {
+        let entry = &config.manifest[path];
+        let in_directory = directory.is_empty() || path.starts_with(&format!("{}/", directory));
+
+        if !in_directory || !matches(&patterns, path) {
+            continue;
+        }
+
+        let encrypted_contents = match std::fs::read_to_string(base.join(&entry.encrypted)) {
+            Ok(val) => val,
+            Err(_) => return Err("Unable to read encrypted file"),
+        };
+        let raw_contents = decrypt_contents(&encryption, &entry.key, &encrypted_contents)?;
+
+        let result = AtomicFile::create(&base.join(path), true).and_then(|mut file| {
+            file.write_all(&raw_contents)?;
+            file.commit()
+        });
+        if result.is_err() {
+            return Err("Unable to write decrypted file");
+        }
+
+        println!("Decrypted {} to {}", entry.encrypted, path);
+    }
+
+    Ok(())
+}
+
+pub fn sync(config_path: &Path, options: &SyncOptions) -> Result<(), &'static str> {
+    let config = Config::get(config_path);
+    let encryption = Encryption { config: &config };
+    let base = base_directory(config_path)?;
+    let patterns = parse_patterns(&options.pattern)?;
+
+    let mut paths: Vec<&String> = config.manifest.keys().collect();
+    paths.sort();
+
+    for path in paths {
+        let entry = &config.manifest[path];
+
+        if !matches(&patterns, path) {
+            continue;
+        }
+
+        if !base.join(path).exists() {
+            println!("Skipped {}, the file does not exist", path);
+            continue;
+        }
+
+        print_result(path, entry, encrypt_entry(&encryption, &base, path, entry)?);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup() -> (tempfile::TempDir, PathBuf) {
+        let directory = tempfile::tempdir().unwrap();
+        let config_path = directory.path().join("secrets.yml");
+        std::fs::copy("test/secrets.yml", &config_path).unwrap();
+
+        let config_directory = directory.path().join("config");
+        std::fs::create_dir_all(config_directory.join("nested")).unwrap();
+        std::fs::write(config_directory.join("database.yml"), "password: hunter2").unwrap();
+        std::fs::write(config_directory.join("nested/key.pem"), "-----BEGIN-----").unwrap();
+
+        (directory, config_path)
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let options = EncryptDirOptions::from_iter(&[
+            "encrypt-dir",
+            "-p",
+            "*.yml",
+            "--pattern",
+            "*.pem",
+            "./config",
+        ]);
+        assert_eq!(options.pattern, vec!["*.yml", "*.pem"]);
+        assert_eq!(options.directory, PathBuf::from("./config"));
+    }
+
+    #[test]
+    fn test_matches() {
+        let patterns = parse_patterns(&["*.yml".to_string()]).unwrap();
+        assert!(matches(&patterns, "config/database.yml"));
+        assert!(!matches(&patterns, "config/key.pem"));
+        assert!(matches(&[], "config/key.pem"));
+    }
+
+    #[test]
+    fn test_encrypt_decrypt_dir() -> Result<(), &'static str> {
+        let (directory, config_path) = setup();
+        let config_directory = directory.path().join("config");
+
+        encrypt_dir(
+            &config_path,
+            &EncryptDirOptions {
+                key: Some("*".to_string()),
+                pattern: vec![],
+                directory: config_directory.clone(),
+            },
+        )?;
+
+        let config = Config::get(&config_path);
+        assert_eq!(config.manifest.len(), 2);
+        assert_eq!(
+            config.manifest["config/nested/key.pem"].encrypted,
+            "config/nested/key.pem.enc"
+        );
+
+        // Unchanged files are not encrypted again:
+        let encrypted_path = config_directory.join("database.yml.enc");
+        let encrypted = std::fs::read_to_string(&encrypted_path).unwrap();
+        sync(&config_path, &SyncOptions { pattern: vec![] })?;
+        assert_eq!(std::fs::read_to_string(&encrypted_path).unwrap(), encrypted);
+
+        std::fs::write(config_directory.join("database.yml"), "password: changed").unwrap();
+        sync(&config_path, &SyncOptions { pattern: vec![] })?;
+        assert_ne!(std::fs::read_to_string(&encrypted_path).unwrap(), encrypted);
+
+        std::fs::remove_file(config_directory.join("database.yml")).unwrap();
+        decrypt_dir(
+            &config_path,
+            &DecryptDirOptions {
+                pattern: vec!["*.yml".to_string()],
+                directory: config_directory.clone(),
+            },
+        )?;
+        assert_eq!(
+            std::fs::read_to_string(config_directory.join("database.yml")).unwrap(),
+            "password: changed"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,128 @@
+use std::env;
+use std::fs::OpenOptions;
+#[cfg(unix)]
+use std::fs::Permissions;
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+/// Returns the directory that private temporary directories are created in. A tmpfs is
+/// preferred, so that decrypted secrets are never written to a disk.
+fn temp_root() -> PathBuf {
+    if let Some(runtime_dir) = env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from) {
+        if runtime_dir.is_dir() {
+            return runtime_dir;
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        let shm = PathBuf::from("/dev/shm");
+        if shm.is_dir() {
+            return shm;
+        }
+    }
+
+    env::temp_dir()
+}
+
+/// A temporary directory that only the current user can access, which holds decrypted
+/// secrets. Every file in the directory is overwritten before the directory is removed,
+/// which happens when it is dropped.
+pub struct PrivateDirectory {
+    directory: Option<TempDir>,
+}
+
+impl PrivateDirectory {
+    pub fn create() -> std::io::Result<PrivateDirectory> {
+        let directory = tempfile::Builder::new()
+            .prefix("scoob")
+            .tempdir_in(temp_root())?;
+        #[cfg(unix)]
+        std::fs::set_permissions(directory.path(), Permissions::from_mode(0o700))?;
+
+        Ok(PrivateDirectory {
+            directory: Some(directory),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        self.directory
+            .as_ref()
+            .expect("The directory was already removed.")
+            .path()
+    }
+
+    /// Writes a file in the directory, which is created with the given permissions. Existing
+    /// files are replaced atomically, so that they can be updated while they are being read.
+    pub fn write_file(&self, name: &str, contents: &[u8], mode: u32) -> std::io::Result<PathBuf> {
+        let path = self.path().join(name);
+        let temp_path = self.path().join(format!(".{}.tmp", name));
+
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        options.mode(mode);
+        #[cfg(not(unix))]
+        let _ = mode;
+
+        let mut file = options.open(&temp_path)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+
+        std::fs::rename(&temp_path, &path)?;
+        Ok(path)
+    }
+}
+
+impl Drop for PrivateDirectory {
+    fn drop(&mut self) {
+        if let Some(directory) = self.directory.take() {
+            if let Ok(entries) = std::fs::read_dir(directory.path()) {
+                for entry in entries.flatten() {
+                    overwrite(&entry.path()).ok();
+                }
+            }
+            directory.close().ok();
+        }
+    }
+}
+
+fn overwrite(path: &Path) -> std::io::Result<()> {
+    // Files can be read-only, so they are made writable before they are overwritten:
+    #[cfg(unix)]
+    std::fs::set_permissions(path, Permissions::from_mode(0o600))?;
+
+    let length = std::fs::metadata(path)?.len();
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    file.write_all(&vec![0u8; length as usize])?;
+    file.sync_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_private_directory() -> std::io::Result<()> {
+        let directory = PrivateDirectory::create()?;
+        let path = directory.write_file("key.pem", b"secret", 0o400)?;
+        assert_eq!(std::fs::read_to_string(&path)?, "secret");
+
+        directory.write_file("key.pem", b"rotated", 0o400)?;
+        assert_eq!(std::fs::read_to_string(&path)?, "rotated");
+
+        #[cfg(unix)]
+        {
+            let file_mode = std::fs::metadata(&path)?.permissions().mode();
+            assert_eq!(file_mode & 0o777, 0o400);
+        }
+
+        drop(directory);
+        assert!(!path.exists());
+        assert!(!path.parent().unwrap().exists());
+        Ok(())
+    }
+}
@@ -0,0 +1,183 @@
+use serde::Deserialize;
+use std::env;
+use std::path::{Path, PathBuf};
+
+/// The name of the project file. Secrets files are often YAML files named after scoob, such as
+/// `scoob.yml`, so the project file has a name that they don't use.
+const PROJECT_FILE: &str = ".scoob.toml";
+
+/// Settings for a project, read from the nearest `.scoob.toml` file, which are used when they
+/// aren't passed on the command line.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Project {
+    /// The directory of the project file, which paths in it are relative to.
+    #[serde(skip)]
+    pub directory: PathBuf,
+    file: Option<PathBuf>,
+    dotenv: Option<bool>,
+    pub keys: KeyDefaults,
+    pub environment: EnvironmentDefaults,
+}
+
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct KeyDefaults {
+    /// The encryption key that files are encrypted with, instead of `*`.
+    pub default: Option<String>,
+    /// The region of AWS Secrets Manager that keys are read from, instead of `us-east-1`.
+    pub aws_region: Option<String>,
+}
+
+/// Defaults for the options of `start` that control the environment of the command.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct EnvironmentDefaults {
+    pub only: Vec<String>,
+    pub exclude: Vec<String>,
+    pub strip_prefix: Option<String>,
+    pub prefix: Option<String>,
+    pub clean_env: bool,
+    pub keep: Vec<String>,
+    pub on_conflict: Option<String>,
+    pub strip_key_vars: bool,
+}
+
+impl Project {
+    /// Reads the project file in the directory, when it has one.
+    pub fn load(directory: &Path) -> Result<Option<Project>, &'static str> {
+        let contents = match std::fs::read_to_string(directory.join(PROJECT_FILE)) {
+            Ok(val) => val,
+            Err(_) => return Ok(None),
+        };
+
+        match toml::from_str::<Project>(&contents) {
+            Ok(val) => Ok(Some(Project {
+                directory: directory.to_path_buf(),
+                ..val
+            })),
+            Err(_) => Err("Unable to parse the project file, please verify that it's valid."),
+        }
+    }
+
+    /// Finds the nearest project file, in the directory or any of its parents.
+    pub fn find(directory: &Path) -> Result<Option<Project>, &'static str> {
+        for ancestor in directory.ancestors() {
+            if let Some(project) = Project::load(ancestor)? {
+                return Ok(Some(project));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Returns the project of the current directory, or an empty project when there is none.
+    pub fn current() -> Result<Project, &'static str> {
+        let current_dir = match env::current_dir() {
+            Ok(val) => val,
+            Err(_) => return Err("Unable to read the current directory."),
+        };
+
+        Ok(Project::find(&current_dir)?.unwrap_or_default())
+    }
+
+    /// The default scoob configuration file.
+    pub fn file(&self) -> Option<PathBuf> {
+        self.file.as_ref().map(|file| self.directory.join(file))
+    }
+
+    /// Whether the `.env` file should be loaded.
+    pub fn loads_dotenv(&self) -> bool {
+        self.dotenv.unwrap_or(true)
+    }
+}
+
+/// Returns the scoob configuration file that was passed on the command line, or set in the
+/// project file.
+pub fn config_file(file: &Option<PathBuf>) -> Result<&Path, &'static str> {
+    match file {
+        Some(val) => Ok(val),
+        None => Err(
+            "No scoob configuration file was provided, and no project file (.scoob.toml) sets one.",
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_project() -> Result<(), &'static str> {
+        let directory = tempfile::tempdir().unwrap();
+        let nested = directory.path().join("app").join("src");
+        std::fs::create_dir_all(&nested).unwrap();
+        assert_eq!(Project::find(&nested)?, None);
+
+        std::fs::write(
+            directory.path().join(".scoob.toml"),
+            "file = \"config/secrets.yml\"\ndotenv = false\n\n[keys]\ndefault = \"web\"\naws-region = \"eu-west-1\"\n\n[environment]\nonly = [\"WEB_*\"]\nclean-env = true\n",
+        )
+        .unwrap();
+
+        let project = Project::find(&nested)?.unwrap();
+        assert_eq!(
+            project.file(),
+            Some(directory.path().join("config/secrets.yml"))
+        );
+        assert!(!project.loads_dotenv());
+        assert_eq!(project.keys.default.as_deref(), Some("web"));
+        assert_eq!(project.keys.aws_region.as_deref(), Some("eu-west-1"));
+        assert_eq!(project.environment.only, vec!["WEB_*"]);
+        assert!(project.environment.clean_env);
+
+        // The nearest project file wins:
+        std::fs::write(
+            directory.path().join("app").join(".scoob.toml"),
+            "file = \"secrets.yml\"\n\n[environment]\nstrip-prefix = \"WEB_\"\n",
+        )
+        .unwrap();
+
+        let project = Project::find(&nested)?.unwrap();
+        assert_eq!(
+            project.file(),
+            Some(directory.path().join("app").join("secrets.yml"))
+        );
+        assert!(project.loads_dotenv());
+        assert_eq!(project.environment.strip_prefix.as_deref(), Some("WEB_"));
+
+        std::fs::write(
+            directory.path().join("app").join(".scoob.toml"),
+            "files = []",
+        )
+        .unwrap();
+        assert!(Project::find(&nested).is_err());
+
+        // An empty project file uses the defaults:
+        std::fs::write(directory.path().join("app").join(".scoob.toml"), "").unwrap();
+        assert_eq!(Project::find(&nested)?.unwrap().file(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_secrets_file_is_not_project() -> Result<(), &'static str> {
+        let directory = tempfile::tempdir().unwrap();
+        std::fs::copy(
+            env::current_dir().unwrap().join("test").join("secrets.yml"),
+            directory.path().join("scoob.yml"),
+        )
+        .unwrap();
+
+        assert_eq!(Project::find(directory.path())?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_file() {
+        assert_eq!(
+            config_file(&Some(PathBuf::from("secrets.yml"))),
+            Ok(Path::new("secrets.yml"))
+        );
+        assert!(config_file(&None).is_err());
+    }
+}
@@ -0,0 +1,133 @@
+use crate::config::Config;
+use crate::encryption::Encryption;
+use crate::template;
+use crate::write::AtomicFile;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+pub struct Render {
+    /// Path to the scoob configuration file
+    #[structopt(parse(from_os_str))]
+    file: PathBuf,
+    /// The template to render, where placeholders such as `{{ NAME }}` or `{{ NAME | json }}`
+    /// are replaced with the values of the secrets, by their `as:` names when they have one
+    #[structopt(parse(from_os_str))]
+    template: PathBuf,
+    /// File that the template will be rendered to, or `-` to write to stdout
+    #[structopt(parse(from_os_str))]
+    out: PathBuf,
+}
+
+/// Renders a template with the decrypted values from the configuration file.
+pub fn render_template(
+    template_path: &Path,
+    values: &HashMap<String, String>,
+) -> Result<String, &'static str> {
+    let contents = match std::fs::read_to_string(template_path) {
+        Ok(val) => val,
+        Err(_) => return Err("Unable to read the template."),
+    };
+
+    template::render(&contents, |key| match values.get(key) {
+        Some(value) => Ok(value.to_string()),
+        None => Err("The template refers to a secret that does not exist."),
+    })
+}
+
+pub fn render(cmd: &Render) -> Result<(), &'static str> {
+    if !Config::exists(&cmd.file) {
+        return Err("The provided configuration file does not exist");
+    }
+
+    let config = Config::get(&cmd.file);
+    let values: HashMap<String, String> = Encryption { config: &config }
+        .decrypt_configuration()?
+        .into_iter()
+        .map(|(key, value)| match config.env.get(&key) {
+            Some(entry) => (entry.name.to_string(), value),
+            None => (key, value),
+        })
+        .collect();
+    let rendered = render_template(&cmd.template, &values)?;
+
+    if cmd.out == Path::new("-") {
+        print!("{}", rendered);
+        return Ok(());
+    }
+
+    // Rendered files hold decrypted secrets, so they're only readable by the owner:
+    let result = AtomicFile::create(&cmd.out, true).and_then(|mut file| {
+        file.write_all(rendered.as_bytes())?;
+        file.commit()
+    });
+
+    match result {
+        Ok(_) => Ok(()),
+        Err(_) => Err("Unable to write the rendered file."),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EnvEntry;
+    use std::env;
+
+    fn get_test_path(file_name: &str) -> PathBuf {
+        let mut path = env::current_dir().unwrap();
+        path.push("test");
+        path.push(file_name);
+        path
+    }
+
+    #[test]
+    fn test_render() -> Result<(), &'static str> {
+        let directory = tempfile::tempdir().unwrap();
+        let out = directory.path().join("config.toml");
+
+        render(&Render {
+            file: get_test_path("secrets.yml"),
+            template: get_test_path("template.toml"),
+            out: out.clone(),
+        })?;
+
+        assert_eq!(
+            std::fs::read_to_string(&out).unwrap(),
+            "[example]\nkey = \"another encrypted key\"\nurl = \"https://example.com/?key=another%20encrypted%20key\"\n"
+        );
+
+        // Secrets with an `as:` name are rendered by that name, the same as in `start`:
+        let config_path = directory.path().join("secrets.yml");
+        let mut config = Config::get(&get_test_path("secrets.yml"));
+        config.env.insert(
+            "EXAMPLE_KEY".to_string(),
+            EnvEntry {
+                name: "TEST_KEY".to_string(),
+            },
+        );
+        config.env.insert(
+            "TEST_KEY".to_string(),
+            EnvEntry {
+                name: "RENAMED_KEY".to_string(),
+            },
+        );
+        crate::write::write(
+            &config_path,
+            serde_yaml::to_string(&config).unwrap().as_bytes(),
+        )
+        .unwrap();
+
+        render(&Render {
+            file: config_path,
+            template: get_test_path("template.toml"),
+            out: out.clone(),
+        })?;
+        assert!(std::fs::read_to_string(&out)
+            .unwrap()
+            .contains("key = \"some value that should be encrypted\""));
+        Ok(())
+    }
+}
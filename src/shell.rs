@@ -0,0 +1,241 @@
+use crate::project::{config_file, EnvironmentDefaults, Project};
+use crate::start::{prepare, Start};
+use crate::supervise::Supervisor;
+use colored::Colorize;
+use std::env;
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+use structopt::StructOpt;
+
+/// How often the session is checked for being idle.
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, StructOpt)]
+pub struct Shell {
+    /// Path to the scoob configuration file, which defaults to the file set in the project file
+    #[structopt(parse(from_os_str))]
+    file: Option<PathBuf>,
+    /// The shell to start, instead of the one in `$SHELL`
+    #[structopt(long, parse(from_os_str))]
+    shell: Option<PathBuf>,
+    /// Close the session after nobody typed into it for this many seconds
+    #[structopt(long)]
+    idle_timeout: Option<u64>,
+    /// The environment settings of the project file, which apply the same way as for `start`
+    #[structopt(skip)]
+    environment: EnvironmentDefaults,
+}
+
+impl Shell {
+    /// Uses the configuration file and the environment settings of the project file, when the
+    /// configuration file wasn't passed.
+    pub fn apply_project(&mut self, project: &Project) {
+        if self.file.is_none() {
+            self.file = project.file();
+        }
+        self.environment = project.environment.clone();
+    }
+}
+
+/// Sessions can't be nested, as it would be unclear which secrets are loaded after exiting
+/// the inner session.
+fn refuse_nesting(active: Option<OsString>) -> Result<(), &'static str> {
+    match active {
+        Some(_) => Err("A scoob shell is already active, exit it before starting another one."),
+        None => Ok(()),
+    }
+}
+
+fn shell_program(cmd: &Shell) -> OsString {
+    if let Some(shell) = &cmd.shell {
+        return shell.clone().into_os_string();
+    }
+
+    match env::var_os(if cfg!(windows) { "COMSPEC" } else { "SHELL" }) {
+        Some(val) if !val.is_empty() => val,
+        _ => OsString::from(if cfg!(windows) { "cmd.exe" } else { "/bin/sh" }),
+    }
+}
+
+/// Returns when someone last typed into the terminal, from the access time of the terminal
+/// device. This is also what `w` reports as the idle time of a session.
+#[cfg(unix)]
+fn terminal_activity() -> Option<SystemTime> {
+    unsafe {
+        if libc::isatty(libc::STDIN_FILENO) != 1 {
+            return None;
+        }
+
+        let mut stat: libc::stat = std::mem::zeroed();
+        if libc::fstat(libc::STDIN_FILENO, &mut stat) != 0 {
+            return None;
+        }
+
+        Some(SystemTime::UNIX_EPOCH + Duration::from_secs(stat.st_atime as u64))
+    }
+}
+
+/// The terminal's activity can't be determined, so the session is idle from when it started.
+#[cfg(not(unix))]
+fn terminal_activity() -> Option<SystemTime> {
+    None
+}
+
+fn is_idle(started: SystemTime, timeout: Duration) -> bool {
+    let last_activity = match terminal_activity() {
+        Some(val) if val > started => val,
+        _ => started,
+    };
+
+    last_activity.elapsed().unwrap_or_default() >= timeout
+}
+
+pub fn shell(cmd: &Shell) -> Result<i32, &'static str> {
+    refuse_nesting(env::var_os("SCOOB_ACTIVE"))?;
+    let file = config_file(&cmd.file)?;
+
+    let mut start = match Start::from_iter_safe(vec![
+        OsString::from("start"),
+        file.as_os_str().to_os_string(),
+        shell_program(cmd),
+    ]) {
+        Ok(val) => val,
+        Err(_) => return Err("Unable to start the shell."),
+    };
+    start.apply_environment(&cmd.environment)?;
+
+    let mut prepared = prepare(&start)?;
+    let active_file = std::fs::canonicalize(file).unwrap_or_else(|_| file.to_path_buf());
+    prepared.command.env("SCOOB_ACTIVE", &active_file);
+
+    // Messages are written to stderr, so that they aren't mixed into the shell's output:
+    eprintln!(
+        "{}",
+        format!(
+            "Loaded the secrets from {}, exit the shell to unload them.",
+            active_file.display()
+        )
+        .green()
+    );
+
+    let supervisor = Supervisor::new();
+    let mut child = supervisor.spawn(&mut prepared.command)?;
+    let started = SystemTime::now();
+
+    let code = match cmd.idle_timeout.map(Duration::from_secs) {
+        None => supervisor.wait(&mut child)?,
+        Some(timeout) => loop {
+            if let Some(code) = supervisor.try_wait(&mut child)? {
+                break code;
+            }
+
+            if is_idle(started, timeout) {
+                eprintln!(
+                    "{}",
+                    "The shell was idle for too long, closing it...".yellow()
+                );
+                // Interactive shells ignore SIGTERM, but exit when their terminal hangs up:
+                #[cfg(unix)]
+                supervisor.signal(&child, libc::SIGHUP);
+                break supervisor.stop(&mut child)?;
+            }
+
+            std::thread::sleep(IDLE_CHECK_INTERVAL);
+        },
+    };
+
+    eprintln!("{}", "Unloaded the secrets.".green());
+    Ok(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(unix)]
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::Path;
+    use std::time::Instant;
+
+    fn get_secrets_path() -> PathBuf {
+        let mut path = env::current_dir().unwrap();
+        path.push("test");
+        path.push("secrets.yml");
+        path
+    }
+
+    /// Writes a script that is started instead of an interactive shell.
+    #[cfg(unix)]
+    fn write_script(path: &Path, contents: &str) {
+        std::fs::write(path, format!("#!/bin/sh\n{}\n", contents)).unwrap();
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    #[test]
+    fn test_refuse_nesting() {
+        assert!(refuse_nesting(None).is_ok());
+        assert!(refuse_nesting(Some(OsString::from("/app/secrets.yml"))).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_shell() -> Result<(), &'static str> {
+        let directory = tempfile::tempdir().unwrap();
+        let script = directory.path().join("shell.sh");
+        let output = directory.path().join("output");
+        write_script(
+            &script,
+            &format!(
+                "echo \"$SCOOB_ACTIVE $TEST_KEY\" > {}\nexit 5",
+                output.display()
+            ),
+        );
+
+        let cmd = Shell {
+            file: Some(get_secrets_path()),
+            shell: Some(script),
+            idle_timeout: None,
+            environment: EnvironmentDefaults::default(),
+        };
+        assert_eq!(shell(&cmd)?, 5);
+        assert_eq!(
+            std::fs::read_to_string(&output).unwrap(),
+            format!("{} another encrypted key\n", get_secrets_path().display())
+        );
+
+        // The environment settings of the project file apply to the shell:
+        let cmd = Shell {
+            environment: EnvironmentDefaults {
+                exclude: vec!["TEST_KEY".to_string()],
+                ..EnvironmentDefaults::default()
+            },
+            ..cmd
+        };
+        assert_eq!(shell(&cmd)?, 5);
+        assert_eq!(
+            std::fs::read_to_string(&output).unwrap(),
+            format!("{} \n", get_secrets_path().display())
+        );
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_shell_idle_timeout() -> Result<(), &'static str> {
+        let directory = tempfile::tempdir().unwrap();
+        let script = directory.path().join("shell.sh");
+        write_script(&script, "exec sleep 30");
+
+        let cmd = Shell {
+            file: Some(get_secrets_path()),
+            shell: Some(script),
+            idle_timeout: Some(1),
+            environment: EnvironmentDefaults::default(),
+        };
+
+        let started = Instant::now();
+        assert_eq!(shell(&cmd)?, 128 + libc::SIGHUP);
+        assert!(started.elapsed() < Duration::from_secs(10));
+        Ok(())
+    }
+}
@@ -1,68 +1,745 @@
-use crate::config::Config;
+use crate::config::{Config, EnvEntry};
 use crate::encryption::Encryption;
+use crate::file::{check_file_name, decrypt_contents};
+use crate::private::PrivateDirectory;
+use crate::project::{config_file, EnvironmentDefaults, Project};
+use crate::render::render_template;
+use crate::supervise::{parse_signal, resolve_program, supervise, Supervisor};
+use crate::template::render;
+use colored::Colorize;
+use glob::Pattern;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs::File;
+#[cfg(unix)]
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::io::FromRawFd;
 #[cfg(unix)]
 use std::os::unix::process::CommandExt;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::str::FromStr;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::time::{Duration, Instant};
 use structopt::StructOpt;
 
+/// How often the configuration file is checked for changes while watching it.
+const WATCH_INTERVAL: Duration = Duration::from_millis(250);
+/// How long the configuration file has to stay unchanged before it is reloaded, so that a
+/// file that is being written is only reloaded once.
+const RELOAD_DEBOUNCE: Duration = Duration::from_secs(1);
+
 #[derive(Debug, PartialEq, StructOpt)]
 pub enum SubCommand {
     #[structopt(external_subcommand)]
     Other(Vec<String>),
 }
 
+/// A template that is rendered before the command starts, given as `TEMPLATE:NAME`.
+#[derive(Debug, PartialEq)]
+pub struct RenderTarget {
+    template: PathBuf,
+    name: String,
+}
+
+impl FromStr for RenderTarget {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<RenderTarget, String> {
+        // The last colon is used, so that templates can be given as Windows paths:
+        match value.rsplit_once(':') {
+            Some((template, name)) if Path::new(name).file_name() == Some(OsStr::new(name)) => {
+                Ok(RenderTarget {
+                    template: PathBuf::from(template),
+                    name: name.to_string(),
+                })
+            }
+            _ => Err(format!(
+                "Templates must be given as TEMPLATE:NAME, where NAME is a file name: {}",
+                value
+            )),
+        }
+    }
+}
+
+/// What happens when a secret has the same name as an environment variable that the command
+/// would otherwise inherit.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Conflict {
+    Override,
+    Keep,
+    Error,
+}
+
+impl FromStr for Conflict {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Conflict, String> {
+        match value {
+            "override" => Ok(Conflict::Override),
+            "keep" => Ok(Conflict::Keep),
+            "error" => Ok(Conflict::Error),
+            _ => Err(format!("Unknown conflict policy: {}", value)),
+        }
+    }
+}
+
 #[derive(Debug, StructOpt)]
 pub struct Start {
-    /// Path to the scoob configuration file
+    /// Path to the scoob configuration file, which defaults to the file set in the project
+    /// file. The command has to follow `--` when it's left out
     #[structopt(parse(from_os_str))]
-    file: PathBuf,
+    file: Option<PathBuf>,
+    /// Keep scoob running as the parent of the command, instead of replacing scoob with it.
+    /// Signals are forwarded to the command, and orphaned processes are reaped when scoob
+    /// runs as PID 1, such as in a Docker container
+    #[structopt(long)]
+    supervise: bool,
+    /// Watch the configuration file, and restart the command when its secrets change. The
+    /// command runs in its own process group, so that the processes it started are stopped
+    /// along with it
+    #[structopt(long)]
+    watch: bool,
+    /// Send this signal, such as `SIGHUP`, to the command instead of restarting it when only
+    /// the contents of files changed. Values are environment variables, which can't change
+    /// while the command runs, so changed values always restart the command
+    #[structopt(long, requires = "watch", parse(try_from_str = parse_signal))]
+    reload_signal: Option<i32>,
+    /// Decrypt the secrets again every this many seconds while watching, even when the
+    /// configuration file did not change, to pick up keys rotated in a remote key provider
+    #[structopt(long, requires = "watch")]
+    poll_interval: Option<u64>,
+    /// Only pass secrets whose names match this name or glob pattern, such as `WEB_*`
+    #[structopt(long, number_of_values = 1)]
+    only: Vec<Pattern>,
+    /// Don't pass secrets whose names match this name or glob pattern
+    #[structopt(long, number_of_values = 1)]
+    exclude: Vec<Pattern>,
+    /// Remove this prefix from the names of the secrets that start with it
+    #[structopt(long)]
+    strip_prefix: Option<String>,
+    /// Add this prefix to the names of the secrets
+    #[structopt(long)]
+    prefix: Option<String>,
+    /// Start the command without inheriting the environment variables of scoob, except for
+    /// the ones passed to `--keep`
+    #[structopt(long)]
+    clean_env: bool,
+    /// An environment variable to keep when using `--clean-env`, such as `PATH`
+    #[structopt(long, requires = "clean-env", number_of_values = 1)]
+    keep: Vec<String>,
+    /// Don't load the `.env` file in the current directory
+    #[structopt(long)]
+    no_dotenv: bool,
+    /// What to do when a secret has the same name as an inherited environment variable:
+    /// `override` it, which is the default, `keep` the inherited variable, or exit with an
+    /// `error`
+    #[structopt(long, possible_values = &["override", "keep", "error"])]
+    on_conflict: Option<Conflict>,
+    /// Remove the environment variables that the encryption keys are read from, so that the
+    /// command can't decrypt the secrets file itself
+    #[structopt(long)]
+    strip_key_vars: bool,
+    /// Render a template with the values of the secrets before starting the command, given as
+    /// `TEMPLATE:NAME`. Templates can use the secrets that are passed to the command, by the
+    /// names of their environment variables. Templates are rendered into a private directory,
+    /// which is passed to the command as `SCOOB_RENDER_DIR`, and are removed when the command
+    /// exits
+    #[structopt(long, number_of_values = 1)]
+    render: Vec<RenderTarget>,
+    /// Replace `{{NAME}}` placeholders in the arguments of the command with the values of the
+    /// secrets, by the names of their environment variables. Arguments can be seen by other users in the process list, unless
+    /// `--template-fd` is used
+    #[structopt(long)]
+    template_args: bool,
+    /// Replace placeholders with a path such as `/dev/fd/3` instead, which the command can
+    /// read the secret from through a pipe that it inherits
+    #[structopt(long, requires = "template-args")]
+    template_fd: bool,
     /// The sub-command that you wish to run
     #[structopt(subcommand)]
-    sub_command: SubCommand,
+    sub_command: Option<SubCommand>,
 }
 
-pub fn start(cmd: &Start) -> Result<i32, &'static str> {
-    if !Config::exists(&cmd.file) {
-        return Err("The provided configuration file does not exist");
+impl Start {
+    /// Whether the `.env` file should be loaded before starting the command.
+    pub fn loads_dotenv(&self) -> bool {
+        !self.no_dotenv
+    }
+
+    /// Uses the settings of the project file for the options that weren't passed. Everything
+    /// after `--` is the command, so when the command directly follows `--`, the first word
+    /// that was parsed as the configuration file is part of the command instead.
+    pub fn apply_project(
+        &mut self,
+        project: &Project,
+        args: &[OsString],
+    ) -> Result<(), &'static str> {
+        let separator = args.iter().position(|arg| arg == "--");
+        let follows_separator = match (separator, &self.file) {
+            (Some(index), Some(file)) => args.get(index + 1).map(Path::new) == Some(file.as_path()),
+            _ => false,
+        };
+
+        if follows_separator {
+            let mut command = vec![self.file.take().unwrap().to_string_lossy().to_string()];
+            if let Some(SubCommand::Other(values)) = self.sub_command.take() {
+                command.extend(values);
+            }
+            self.sub_command = Some(SubCommand::Other(command));
+        }
+
+        if self.file.is_none() {
+            self.file = project.file();
+        }
+
+        self.apply_environment(&project.environment)
     }
 
-    let config = Config::get(&cmd.file);
+    /// Uses the environment settings of the project file for the options that weren't passed.
+    pub fn apply_environment(
+        &mut self,
+        environment: &EnvironmentDefaults,
+    ) -> Result<(), &'static str> {
+        if self.only.is_empty() {
+            self.only = parse_patterns(&environment.only)?;
+        }
+        if self.exclude.is_empty() {
+            self.exclude = parse_patterns(&environment.exclude)?;
+        }
+        if self.strip_prefix.is_none() {
+            self.strip_prefix = environment.strip_prefix.clone();
+        }
+        if self.prefix.is_none() {
+            self.prefix = environment.prefix.clone();
+        }
+        if self.keep.is_empty() {
+            self.keep = environment.keep.clone();
+        }
+        if self.on_conflict.is_none() {
+            self.on_conflict = match &environment.on_conflict {
+                Some(val) => match Conflict::from_str(val) {
+                    Ok(val) => Some(val),
+                    Err(_) => return Err("The project file has an unknown on-conflict policy. The available policies are override, keep and error."),
+                },
+                None => None,
+            };
+        }
+        self.clean_env = self.clean_env || environment.clean_env;
+        self.strip_key_vars = self.strip_key_vars || environment.strip_key_vars;
+        Ok(())
+    }
+    /// Returns the name of the environment variable that a secret is passed as, or `None`
+    /// when the secret is filtered out. Names from the `env` section of the configuration
+    /// file are used as they are, other names get the prefix options applied.
+    fn env_name(&self, env: &HashMap<String, EnvEntry>, key: &str) -> Option<String> {
+        let included = self.only.is_empty() || self.only.iter().any(|p| p.matches(key));
+        if !included || self.exclude.iter().any(|p| p.matches(key)) {
+            return None;
+        }
 
+        if let Some(entry) = env.get(key) {
+            return Some(entry.name.to_string());
+        }
+
+        let name = match &self.strip_prefix {
+            Some(prefix) => key.strip_prefix(prefix.as_str()).unwrap_or(key),
+            None => key,
+        };
+
+        Some(format!("{}{}", self.prefix.as_deref().unwrap_or(""), name))
+    }
+}
+
+fn parse_patterns(patterns: &[String]) -> Result<Vec<Pattern>, &'static str> {
+    patterns
+        .iter()
+        .map(|pattern| match Pattern::new(pattern) {
+            Ok(val) => Ok(val),
+            Err(_) => Err("The project file has an invalid pattern."),
+        })
+        .collect()
+}
+
+/// The decrypted secrets that a command is started with.
+#[derive(PartialEq)]
+struct Secrets {
+    values: HashMap<String, String>,
+    files: HashMap<String, Vec<u8>>,
+    env: HashMap<String, EnvEntry>,
+    key_variables: Vec<String>,
+}
+
+impl Secrets {
+    fn decrypt(config: &Config) -> Result<Secrets, &'static str> {
+        let encryption = Encryption { config };
+        let mut files: HashMap<String, Vec<u8>> = HashMap::new();
+
+        for (name, contents) in config.files.iter() {
+            check_file_name(config, name)?;
+
+            files.insert(
+                name.to_string(),
+                decrypt_contents(&encryption, name, contents)?,
+            );
+        }
+
+        Ok(Secrets {
+            values: encryption.decrypt_configuration()?,
+            files,
+            env: config.env.clone(),
+            key_variables: encryption.key_variables(),
+        })
+    }
+
+    /// Whether the secrets only differ in the contents of files, which can be updated while
+    /// the command runs.
+    fn only_contents_changed(&self, other: &Secrets) -> bool {
+        self.values == other.values
+            && self.env == other.env
+            && self.files.len() == other.files.len()
+            && self.files.keys().all(|name| other.files.contains_key(name))
+    }
+}
+
+/// Writes the decrypted files that are passed to the command to the private directory, and
+/// returns their paths.
+fn write_files(
+    cmd: &Start,
+    directory: &PrivateDirectory,
+    secrets: &Secrets,
+) -> Result<HashMap<String, PathBuf>, &'static str> {
+    let mut paths: HashMap<String, PathBuf> = HashMap::new();
+
+    for (name, contents) in secrets.files.iter() {
+        if cmd.env_name(&secrets.env, name).is_none() {
+            continue;
+        }
+
+        match directory.write_file(name, contents, 0o400) {
+            Ok(path) => paths.insert(name.to_string(), path),
+            Err(_) => return Err("Unable to write decrypted file"),
+        };
+    }
+
+    Ok(paths)
+}
+
+/// Renders the templates into the private directory, with the values that are passed to the
+/// command.
+fn render_templates(
+    cmd: &Start,
+    directory: &PrivateDirectory,
+    secrets: &Secrets,
+) -> Result<(), &'static str> {
+    let values: HashMap<String, String> = secrets
+        .values
+        .iter()
+        .filter_map(|(key, value)| Some((cmd.env_name(&secrets.env, key)?, value.to_string())))
+        .collect();
+
+    for target in cmd.render.iter() {
+        if secrets.files.contains_key(&target.name) {
+            return Err("A rendered template has the same name as a file in the configuration.");
+        }
+
+        let rendered = render_template(&target.template, &values)?;
+        if directory
+            .write_file(&target.name, rendered.as_bytes(), 0o400)
+            .is_err()
+        {
+            return Err("Unable to write the rendered file.");
+        }
+    }
+
+    Ok(())
+}
+
+/// Renames the secrets to the names of their environment variables, leaving out the secrets
+/// that are filtered out.
+fn rename_secrets(
+    cmd: &Start,
+    env: &HashMap<String, EnvEntry>,
+    secrets: impl Iterator<Item = (String, OsString)>,
+) -> Result<Vec<(String, OsString)>, &'static str> {
+    let mut names: HashSet<String> = HashSet::new();
+    let mut renamed: Vec<(String, OsString)> = Vec::new();
+
+    for (key, value) in secrets {
+        if let Some(name) = cmd.env_name(env, &key) {
+            if !names.insert(name.to_string()) {
+                return Err("More than one secret is passed as the same environment variable.");
+            }
+            renamed.push((name, value));
+        }
+    }
+
+    Ok(renamed)
+}
+
+/// Builds the environment of the command from the environment that it inherits, and the
+/// secrets, which are either values or the paths of decrypted files.
+fn child_environment(
+    cmd: &Start,
+    inherited: impl Iterator<Item = (OsString, OsString)>,
+    secrets: impl Iterator<Item = (String, OsString)>,
+    key_variables: &[String],
+) -> Result<HashMap<OsString, OsString>, &'static str> {
+    let mut environment: HashMap<OsString, OsString> = inherited
+        .filter(|(key, _)| !cmd.clean_env || cmd.keep.iter().any(|k| OsStr::new(k) == key))
+        .collect();
+
+    if cmd.strip_key_vars {
+        for variable in key_variables.iter() {
+            environment.remove(OsStr::new(variable));
+        }
+    }
+
+    for (key, value) in secrets {
+        let key = OsString::from(key);
+
+        if environment.contains_key(&key) {
+            match cmd.on_conflict.unwrap_or(Conflict::Override) {
+                Conflict::Override => (),
+                Conflict::Keep => continue,
+                Conflict::Error => {
+                    return Err("A secret has the same name as an environment variable. Use --on-conflict to override or keep the environment variable.")
+                }
+            }
+        }
+
+        environment.insert(key, value);
+    }
+
+    Ok(environment)
+}
+
+/// Passes a secret to the command through a pipe that it inherits, and returns the path that
+/// the command can read the secret from. The secret is written to the pipe before the command
+/// starts, so it has to fit in the buffer of the pipe.
+#[cfg(unix)]
+fn pipe_secret(value: &[u8], pipes: &mut Vec<File>) -> Result<String, &'static str> {
+    let mut fds: [libc::c_int; 2] = [0; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        return Err("Unable to create a pipe to pass a secret through.");
+    }
+
+    // Only the read end is inherited by the command, so that it sees the end of the secret:
+    let (read_end, mut write_end) = unsafe {
+        libc::fcntl(fds[1], libc::F_SETFD, libc::FD_CLOEXEC);
+        libc::fcntl(fds[1], libc::F_SETFL, libc::O_NONBLOCK);
+        (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1]))
+    };
+
+    if write_end.write_all(value).is_err() {
+        return Err("A secret is too large to pass through a pipe.");
+    }
+
+    pipes.push(read_end);
+    Ok(format!("/dev/fd/{}", fds[0]))
+}
+
+#[cfg(not(unix))]
+fn pipe_secret(_value: &[u8], _pipes: &mut Vec<File>) -> Result<String, &'static str> {
+    Err("Passing secrets through pipes is only supported on Unix.")
+}
+
+/// A command that is ready to run, along with what has to stay around while it runs.
+pub struct PreparedCommand {
+    pub command: Command,
+    /// Decrypted files, which are removed when the directory is dropped.
+    files: Option<PrivateDirectory>,
+    /// The read ends of the pipes that secrets are passed through.
+    _pipes: Vec<File>,
+}
+
+/// Looks up an environment variable of the command. Names are case insensitive on Windows,
+/// where `PATH` is usually named `Path`.
+fn env_value<'a>(environment: &'a HashMap<OsString, OsString>, name: &str) -> Option<&'a OsStr> {
+    environment
+        .iter()
+        .find(|(key, _)| {
+            if cfg!(windows) {
+                key.to_string_lossy().eq_ignore_ascii_case(name)
+            } else {
+                key.as_os_str() == name
+            }
+        })
+        .map(|(_, value)| value.as_os_str())
+}
+
+/// Builds the command to run. Decrypted files are written to a private directory, and their
+/// environment variables are set to the paths of the decrypted files.
+fn prepare_command(cmd: &Start, secrets: &Secrets) -> Result<PreparedCommand, &'static str> {
     let mut sub_command = match &cmd.sub_command {
-        SubCommand::Other(values) => values.iter(),
+        Some(SubCommand::Other(values)) => values.iter(),
+        None => return Err("No command was provided."),
+    };
+
+    let first_command = match sub_command.next() {
+        Some(val) => val,
+        None => return Err("No command was provided."),
+    };
+
+    let has_files = secrets
+        .files
+        .keys()
+        .any(|name| cmd.env_name(&secrets.env, name).is_some());
+
+    let (files, file_paths) = if has_files || !cmd.render.is_empty() {
+        let directory = match PrivateDirectory::create() {
+            Ok(val) => val,
+            Err(_) => return Err("Unable to create a private directory for the decrypted files."),
+        };
+        let file_paths = write_files(cmd, &directory, secrets)?;
+        render_templates(cmd, &directory, secrets)?;
+        (Some(directory), file_paths)
+    } else {
+        (None, HashMap::new())
     };
 
-    let first_command = sub_command.next();
+    let values = secrets
+        .values
+        .iter()
+        .map(|(key, value)| (key.to_string(), OsString::from(value)));
+    let paths = file_paths
+        .iter()
+        .map(|(key, path)| (key.to_string(), path.clone().into_os_string()));
+    let renamed = rename_secrets(cmd, &secrets.env, values.chain(paths))?;
+
+    // Placeholders use the names of the environment variables, the same as rendered templates:
+    let mut pipes: Vec<File> = Vec::new();
+    let mut template_value = |name: &str| {
+        let value = match renamed.iter().find(|(env_name, _)| env_name == name) {
+            Some((_, value)) => value.to_string_lossy().to_string(),
+            None => {
+                return Err("An argument refers to a secret that is not passed to the command.")
+            }
+        };
+
+        if cmd.template_fd {
+            pipe_secret(value.as_bytes(), &mut pipes)
+        } else {
+            Ok(value)
+        }
+    };
 
-    if first_command.is_none() {
-        return Err("No command was provided.");
+    let mut args: Vec<String> = Vec::new();
+    for arg in sub_command {
+        if cmd.template_args {
+            args.push(render(arg, &mut template_value)?);
+        } else {
+            args.push(arg.to_string());
+        }
+    }
+
+    if cmd.template_args && !cmd.template_fd {
+        eprintln!(
+            "{}",
+            "Warning: secrets in the arguments of a command can be seen by other users in the process list. Use --template-fd to pass them through pipes instead."
+                .yellow()
+        );
     }
 
-    let mut command = Command::new(first_command.expect("Missing command."));
+    let environment = child_environment(
+        cmd,
+        env::vars_os(),
+        renamed.into_iter(),
+        &secrets.key_variables,
+    )?;
 
-    let encryption = Encryption { config: &config };
+    let mut command = Command::new(resolve_program(
+        first_command,
+        env_value(&environment, "PATH"),
+        env_value(&environment, "PATHEXT"),
+    ));
+    command.env_clear().envs(environment);
+    command.args(args);
 
-    for (key, value) in config.configuration.iter() {
-        command.env(key, encryption.decrypt(key, value)?);
+    if !cmd.render.is_empty() {
+        if let Some(directory) = &files {
+            command.env("SCOOB_RENDER_DIR", directory.path());
+        }
     }
 
-    for arg in sub_command {
-        command.arg(arg);
+    Ok(PreparedCommand {
+        command,
+        files,
+        _pipes: pipes,
+    })
+}
+
+fn reload_secrets(contents: Option<&str>) -> Result<Secrets, &'static str> {
+    let contents = match contents {
+        Some(val) => val,
+        None => return Err("Unable to read the scoob configuration file."),
+    };
+
+    Secrets::decrypt(&Config::from_yaml(contents)?)
+}
+
+/// Messages are written to stderr, so that they aren't mixed into the command's output.
+fn report_reload_error(err: &str) {
+    eprintln!(
+        "{} {}",
+        "Unable to reload secrets, the command keeps running with the previous secrets:"
+            .red()
+            .bold(),
+        err
+    );
+}
+
+/// Watches the configuration file, and sends its contents once they changed and stayed the
+/// same for `RELOAD_DEBOUNCE`, or once the poll interval passed. Stops once the receiver is
+/// dropped.
+fn watch_file(file: PathBuf, poll_interval: Option<Duration>, reloads: Sender<Option<String>>) {
+    let mut loaded_contents = std::fs::read_to_string(&file).ok();
+    let mut seen_contents = loaded_contents.clone();
+    let mut last_change = Instant::now();
+    let mut last_reload = Instant::now();
+
+    loop {
+        std::thread::sleep(WATCH_INTERVAL);
+
+        let contents = std::fs::read_to_string(&file).ok();
+        if contents != seen_contents {
+            seen_contents = contents;
+            last_change = Instant::now();
+        }
+
+        let changed = seen_contents != loaded_contents && last_change.elapsed() >= RELOAD_DEBOUNCE;
+        let poll = poll_interval.is_some_and(|interval| last_reload.elapsed() >= interval);
+        if !changed && !poll {
+            continue;
+        }
+
+        loaded_contents = seen_contents.clone();
+        last_reload = Instant::now();
+
+        if reloads.send(loaded_contents.clone()).is_err() {
+            return;
+        }
     }
+}
 
-    // Only attempt to exec on unix, and when we're not running tests
-    if cfg!(unix) && !cfg!(test) {
-        #[cfg(unix)]
-        command.exec();
-        Ok(0)
-    } else {
-        let status = match command.status() {
+/// Runs the command, and restarts it with the new secrets whenever the secrets in the
+/// configuration file change. When the new secrets can't be decrypted, or the command can't
+/// be prepared with them, the command keeps running with the previous secrets.
+fn watch(cmd: &Start, secrets: Secrets) -> Result<i32, &'static str> {
+    let file = config_file(&cmd.file)?.to_path_buf();
+    let poll_interval = cmd.poll_interval.map(Duration::from_secs);
+
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || watch_file(file, poll_interval, sender));
+    run_watched(cmd, secrets, &receiver)
+}
+
+/// Runs the command, and reloads its secrets from the contents of the configuration file
+/// that are received, until the command exits.
+fn run_watched(
+    cmd: &Start,
+    mut secrets: Secrets,
+    reloads: &Receiver<Option<String>>,
+) -> Result<i32, &'static str> {
+    let supervisor = Supervisor::with_process_group();
+
+    let mut prepared = prepare_command(cmd, &secrets)?;
+    let mut child = supervisor.spawn(&mut prepared.command)?;
+
+    loop {
+        if let Some(code) = supervisor.try_wait(&mut child)? {
+            return Ok(code);
+        }
+
+        let contents = match reloads.recv_timeout(WATCH_INTERVAL) {
+            Ok(val) => val,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => {
+                std::thread::sleep(WATCH_INTERVAL);
+                continue;
+            }
+        };
+
+        let new_secrets = match reload_secrets(contents.as_deref()) {
             Ok(val) => val,
-            Err(_) => return Err("Failed to start command, please verify that it exists."),
+            Err(err) => {
+                report_reload_error(err);
+                continue;
+            }
         };
 
-        Ok(status.code().unwrap_or(0))
+        if new_secrets == secrets {
+            continue;
+        }
+
+        match (cmd.reload_signal, &prepared.files) {
+            (Some(signal), Some(directory)) if new_secrets.only_contents_changed(&secrets) => {
+                if let Err(err) = write_files(cmd, directory, &new_secrets) {
+                    report_reload_error(err);
+                    continue;
+                }
+                eprintln!("{}", "Secrets changed, signalling the command...".yellow());
+                #[cfg(unix)]
+                supervisor.signal(&child, signal);
+                #[cfg(not(unix))]
+                let _ = signal;
+            }
+            _ => {
+                // The new command is prepared first, so that a bad change to the configuration
+                // file doesn't stop the running command:
+                let new_prepared = match prepare_command(cmd, &new_secrets) {
+                    Ok(val) => val,
+                    Err(err) => {
+                        report_reload_error(err);
+                        continue;
+                    }
+                };
+
+                eprintln!("{}", "Secrets changed, restarting the command...".yellow());
+                supervisor.stop(&mut child)?;
+
+                prepared = new_prepared;
+                child = supervisor.spawn(&mut prepared.command)?;
+            }
+        }
+
+        secrets = new_secrets;
+    }
+}
+
+fn load_secrets(cmd: &Start) -> Result<Secrets, &'static str> {
+    let file = config_file(&cmd.file)?;
+    if !Config::exists(file) {
+        return Err("The provided configuration file does not exist");
+    }
+
+    Secrets::decrypt(&Config::get(file))
+}
+
+/// Decrypts the secrets and builds the command, for callers that run the command themselves.
+pub fn prepare(cmd: &Start) -> Result<PreparedCommand, &'static str> {
+    prepare_command(cmd, &load_secrets(cmd)?)
+}
+
+pub fn start(cmd: &Start) -> Result<i32, &'static str> {
+    let secrets = load_secrets(cmd)?;
+
+    if cmd.watch {
+        return watch(cmd, secrets);
+    }
+
+    let mut prepared = prepare_command(cmd, &secrets)?;
+
+    // Only attempt to exec on unix, and when we're not running tests. Decrypted files have to be
+    // removed when the command exits, so scoob keeps running alongside it when there are any.
+    if cfg!(unix) && !cfg!(test) && !cmd.supervise && prepared.files.is_none() {
+        #[cfg(unix)]
+        let _ = prepared.command.exec();
+        Err("Failed to start command, please verify that it exists.")
+    } else {
+        supervise(&mut prepared.command)
     }
 }
 
@@ -71,41 +748,423 @@ mod tests {
     use super::*;
     use std::env;
 
-    fn get_secrets_path() -> PathBuf {
+    fn get_test_path(file_name: &str) -> PathBuf {
         let mut path = env::current_dir().unwrap();
         path.push("test");
-        path.push("secrets.yml");
+        path.push(file_name);
         path
     }
 
+    fn get_secrets_path() -> PathBuf {
+        get_test_path("secrets.yml")
+    }
+
+    fn start_args(file: &Path, args: &[&str]) -> Start {
+        let file = file.to_string_lossy();
+        Start::from_iter(["start", &file].iter().chain(args.iter()))
+    }
+
+    /// Copies the test configuration file, with an encrypted file added to it.
+    fn write_config_with_file(path: &Path, contents: &[u8]) -> Result<(), &'static str> {
+        let mut config = Config::get(&get_secrets_path());
+        let encrypted_contents =
+            crate::file::encrypt_contents(&Encryption { config: &config }, "TLS_KEY", contents)?;
+        config
+            .files
+            .insert("TLS_KEY".to_string(), encrypted_contents);
+        crate::write::write(path, serde_yaml::to_string(&config).unwrap().as_bytes()).unwrap();
+        Ok(())
+    }
+
     #[test]
     fn test_start_no_command() {
         assert!(start(&Start {
-            file: get_secrets_path(),
-            sub_command: SubCommand::Other(vec![])
+            sub_command: Some(SubCommand::Other(vec![])),
+            ..start_args(&get_secrets_path(), &["sh"])
         })
         .is_err());
     }
 
     #[test]
     fn test_start_invalid_command() {
-        assert!(start(&Start {
-            file: get_secrets_path(),
-            sub_command: SubCommand::Other(vec!["command_does_not_exist".to_string()])
-        })
+        assert!(start(&start_args(
+            &get_secrets_path(),
+            &["command_does_not_exist"]
+        ))
         .is_err());
     }
 
     #[test]
-    fn test_start_print() {
+    fn test_start_files() -> Result<(), &'static str> {
+        let directory = tempfile::tempdir().unwrap();
+        let config_path = directory.path().join("secrets.yml");
+        let path_file = directory.path().join("path");
+        write_config_with_file(&config_path, b"secret")?;
+
+        let code = start(&start_args(
+            &config_path,
+            &[
+                "sh",
+                "-c",
+                "test \"$(cat \"$TLS_KEY\")\" = secret && printf %s \"$TLS_KEY\" > \"$1\"",
+                "sh",
+                &path_file.to_string_lossy(),
+            ],
+        ))?;
+        assert_eq!(code, 0);
+
+        // The decrypted file is removed once the command exits:
+        let decrypted_path = std::fs::read_to_string(&path_file).unwrap();
+        assert!(!Path::new(&decrypted_path).exists());
+        Ok(())
+    }
+
+    /// Waits until the file has the number of lines, such as one for each start of a command.
+    fn wait_for_lines(path: &Path, count: usize) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while Instant::now() < deadline {
+            let lines =
+                std::fs::read_to_string(path).map_or(0, |contents| contents.lines().count());
+            if lines >= count {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        panic!("Timed out waiting for {:?} to have {} lines", path, count);
+    }
+
+    #[test]
+    fn test_start_watch() -> Result<(), &'static str> {
+        let directory = tempfile::tempdir().unwrap();
+        let config_path = directory.path().join("secrets.yml");
+        let starts_file = directory.path().join("starts");
+        write_config_with_file(&config_path, b"first")?;
+
+        // The command records each start, and exits once it is started with the new file:
+        let cmd = start_args(
+            &config_path,
+            &[
+                "--watch",
+                "sh",
+                "-c",
+                "echo started >> \"$1\"; while [ \"$(cat \"$TLS_KEY\")\" != rotated ]; do sleep 0.1; done",
+                "sh",
+                &starts_file.to_string_lossy(),
+            ],
+        );
+        let (sender, receiver) = mpsc::channel();
+        let handle = std::thread::spawn(move || run_watched(&cmd, load_secrets(&cmd)?, &receiver));
+
+        wait_for_lines(&starts_file, 1);
+        write_config_with_file(&config_path, b"rotated")?;
+        sender
+            .send(std::fs::read_to_string(&config_path).ok())
+            .unwrap();
+
+        assert_eq!(handle.join().unwrap()?, 0);
+        let starts = std::fs::read_to_string(&starts_file).unwrap();
+        assert_eq!(starts.lines().count(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_start_watch_failed_reload() -> Result<(), &'static str> {
+        let directory = tempfile::tempdir().unwrap();
+        let config_path = directory.path().join("secrets.yml");
+        let starts_file = directory.path().join("starts");
+        let stop_file = directory.path().join("stop");
+        write_config_with_file(&config_path, b"first")?;
+
+        let cmd = start_args(
+            &config_path,
+            &[
+                "--watch",
+                "--template-args",
+                "sh",
+                "-c",
+                "echo \"$1\" >> \"$2\"; while [ ! -e \"$3\" ]; do sleep 0.1; done",
+                "sh",
+                "{{TEST_KEY}}",
+                &starts_file.to_string_lossy(),
+                &stop_file.to_string_lossy(),
+            ],
+        );
+
+        // Sends only complete once the command takes the reload, so the second send waits
+        // for the first reload to be handled:
+        let (sender, receiver) = mpsc::sync_channel(0);
+        let handle = std::thread::spawn(move || run_watched(&cmd, load_secrets(&cmd)?, &receiver));
+        wait_for_lines(&starts_file, 1);
+
+        // Removing a secret that an argument refers to can't restart the command:
+        let mut config = Config::get(&config_path);
+        config.configuration.remove("TEST_KEY");
+        let contents = serde_yaml::to_string(&config).unwrap();
+        sender.send(Some(contents.clone())).unwrap();
+        sender.send(Some(contents)).unwrap();
+
+        std::fs::write(&stop_file, "").unwrap();
+        assert_eq!(handle.join().unwrap()?, 0);
+        let starts = std::fs::read_to_string(&starts_file).unwrap();
         assert_eq!(
-            start(&Start {
-                file: get_secrets_path(),
-                sub_command: SubCommand::Other(vec![
-                    "sh".to_string(),
-                    "./test/compare.sh".to_string()
-                ])
+            starts.lines().collect::<Vec<&str>>(),
+            vec!["another encrypted key"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_child_environment() -> Result<(), &'static str> {
+        let inherited = || {
+            vec![
+                (OsString::from("PATH"), OsString::from("/bin")),
+                (OsString::from("API_TOKEN"), OsString::from("inherited")),
+                (OsString::from("SCOOB_SECRET_KEY"), OsString::from("key")),
+            ]
+            .into_iter()
+        };
+        let secrets = || vec![("API_TOKEN".to_string(), OsString::from("secret"))].into_iter();
+        let key_variables = vec!["SCOOB_SECRET_KEY".to_string()];
+
+        let cmd = start_args(&get_secrets_path(), &["sh"]);
+        let environment = child_environment(&cmd, inherited(), secrets(), &key_variables)?;
+        assert_eq!(environment.len(), 3);
+        assert_eq!(environment[OsStr::new("API_TOKEN")], "secret");
+
+        let cmd = start_args(
+            &get_secrets_path(),
+            &["--clean-env", "--keep", "PATH", "--strip-key-vars", "sh"],
+        );
+        let environment = child_environment(&cmd, inherited(), secrets(), &key_variables)?;
+        assert_eq!(environment.len(), 2);
+        assert_eq!(environment[OsStr::new("PATH")], "/bin");
+
+        let cmd = start_args(&get_secrets_path(), &["--on-conflict", "keep", "sh"]);
+        let environment = child_environment(&cmd, inherited(), secrets(), &key_variables)?;
+        assert_eq!(environment[OsStr::new("API_TOKEN")], "inherited");
+
+        let cmd = start_args(&get_secrets_path(), &["--on-conflict", "error", "sh"]);
+        assert!(child_environment(&cmd, inherited(), secrets(), &key_variables).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_env_name() {
+        let mut env: HashMap<String, EnvEntry> = HashMap::new();
+        env.insert(
+            "WEB_SESSION_SECRET".to_string(),
+            EnvEntry {
+                name: "SECRET_KEY_BASE".to_string(),
+            },
+        );
+
+        let cmd = start_args(
+            &get_secrets_path(),
+            &[
+                "--only",
+                "WEB_*",
+                "--only",
+                "SHARED_TOKEN",
+                "--exclude",
+                "*_UNUSED",
+                "--strip-prefix",
+                "WEB_",
+                "--prefix",
+                "APP_",
+                "sh",
+            ],
+        );
+        assert_eq!(
+            cmd.env_name(&env, "WEB_DATABASE_URL"),
+            Some("APP_DATABASE_URL".to_string())
+        );
+        assert_eq!(
+            cmd.env_name(&env, "SHARED_TOKEN"),
+            Some("APP_SHARED_TOKEN".to_string())
+        );
+        assert_eq!(
+            cmd.env_name(&env, "WEB_SESSION_SECRET"),
+            Some("SECRET_KEY_BASE".to_string())
+        );
+        assert_eq!(cmd.env_name(&env, "WEB_UNUSED"), None);
+        assert_eq!(cmd.env_name(&env, "WORKER_QUEUE_URL"), None);
+
+        let secrets = vec![
+            ("WEB_TOKEN".to_string(), OsString::from("a")),
+            ("TOKEN".to_string(), OsString::from("b")),
+        ];
+        let cmd = start_args(&get_secrets_path(), &["--strip-prefix", "WEB_", "sh"]);
+        assert!(rename_secrets(&cmd, &env, secrets.into_iter()).is_err());
+    }
+
+    #[test]
+    fn test_apply_project() -> Result<(), &'static str> {
+        let directory = tempfile::tempdir().unwrap();
+        std::fs::write(
+            directory.path().join(".scoob.toml"),
+            "file = \"secrets.yml\"\n\n[environment]\nprefix = \"APP_\"\non-conflict = \"keep\"\n",
+        )
+        .unwrap();
+        let project = Project::find(directory.path())?.unwrap();
+        let parse = |args: &[&str]| {
+            let args: Vec<OsString> = args.iter().map(OsString::from).collect();
+            (Start::from_iter(args[1..].iter()), args)
+        };
+
+        let (mut cmd, args) = parse(&["scoob", "start", "--", "npm", "run", "dev"]);
+        cmd.apply_project(&project, &args)?;
+        assert_eq!(cmd.file, Some(directory.path().join("secrets.yml")));
+        assert_eq!(
+            cmd.sub_command,
+            Some(SubCommand::Other(vec![
+                "npm".to_string(),
+                "run".to_string(),
+                "dev".to_string()
+            ]))
+        );
+        assert_eq!(cmd.prefix.as_deref(), Some("APP_"));
+        assert_eq!(cmd.on_conflict, Some(Conflict::Keep));
+
+        let (mut cmd, args) = parse(&["scoob", "start", "--watch", "--", "node"]);
+        cmd.apply_project(&project, &args)?;
+        assert_eq!(
+            cmd.sub_command,
+            Some(SubCommand::Other(vec!["node".to_string()]))
+        );
+
+        // Options passed on the command line win over the project file:
+        let (mut cmd, args) = parse(&[
+            "scoob",
+            "start",
+            "--prefix",
+            "WEB_",
+            "other.yml",
+            "--",
+            "sh",
+        ]);
+        cmd.apply_project(&project, &args)?;
+        assert_eq!(cmd.file, Some(PathBuf::from("other.yml")));
+        assert_eq!(
+            cmd.sub_command,
+            Some(SubCommand::Other(vec!["sh".to_string()]))
+        );
+        assert_eq!(cmd.prefix.as_deref(), Some("WEB_"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_start_template_args() -> Result<(), &'static str> {
+        let script = "test \"$1\" = 'key: another encrypted key'";
+        let args = [
+            "--template-args",
+            "sh",
+            "-c",
+            script,
+            "sh",
+            "key: {{TEST_KEY}}",
+        ];
+        assert_eq!(start(&start_args(&get_secrets_path(), &args))?, 0);
+
+        #[cfg(unix)]
+        {
+            let script = "test \"$(cat \"$1\")\" = 'another encrypted key'";
+            let args = [
+                "--template-args",
+                "--template-fd",
+                "sh",
+                "-c",
+                script,
+                "sh",
+                "{{TEST_KEY}}",
+            ];
+            assert_eq!(start(&start_args(&get_secrets_path(), &args))?, 0);
+        }
+
+        let args = [
+            "--template-args",
+            "--exclude",
+            "TEST_KEY",
+            "echo",
+            "{{TEST_KEY}}",
+        ];
+        assert!(start(&start_args(&get_secrets_path(), &args)).is_err());
+
+        // Placeholders use the names of the environment variables, like rendered templates:
+        let script = "test \"$1\" = \"$APP_TEST_KEY\"";
+        let args = [
+            "--template-args",
+            "--prefix",
+            "APP_",
+            "sh",
+            "-c",
+            script,
+            "sh",
+            "{{APP_TEST_KEY}}",
+        ];
+        assert_eq!(start(&start_args(&get_secrets_path(), &args))?, 0);
+
+        let args = [
+            "--template-args",
+            "--prefix",
+            "APP_",
+            "echo",
+            "{{TEST_KEY}}",
+        ];
+        assert!(start(&start_args(&get_secrets_path(), &args)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_start_render() -> Result<(), &'static str> {
+        let template = get_test_path("template.toml");
+        let render = format!("{}:config.toml", template.to_string_lossy());
+        let script = "grep -q 'key = \"another encrypted key\"' \"$SCOOB_RENDER_DIR/config.toml\"";
+
+        let args = ["--render", &render, "sh", "-c", script];
+        assert_eq!(start(&start_args(&get_secrets_path(), &args))?, 0);
+
+        // Templates can't use secrets that aren't passed to the command:
+        let args = ["--render", &render, "--exclude", "TEST_KEY", "true"];
+        assert!(start(&start_args(&get_secrets_path(), &args)).is_err());
+        let args = ["--render", &render, "--prefix", "APP_", "true"];
+        assert!(start(&start_args(&get_secrets_path(), &args)).is_err());
+
+        assert_eq!(
+            "template.toml:config.toml".parse(),
+            Ok(RenderTarget {
+                template: PathBuf::from("template.toml"),
+                name: "config.toml".to_string()
             })
+        );
+        assert!("template.toml:config/app.toml"
+            .parse::<RenderTarget>()
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_start_exit_code() -> Result<(), &'static str> {
+        let args = ["sh", "-c", "exit 7"];
+        assert_eq!(start(&start_args(&get_secrets_path(), &args))?, 7);
+
+        #[cfg(unix)]
+        {
+            let args = ["sh", "-c", "kill -INT $$"];
+            assert_eq!(
+                start(&start_args(&get_secrets_path(), &args))?,
+                128 + libc::SIGINT
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_start_print() {
+        assert_eq!(
+            start(&start_args(
+                &get_secrets_path(),
+                &["sh", "./test/compare.sh"]
+            ))
             .unwrap(),
             0
         );
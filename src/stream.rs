@@ -0,0 +1,188 @@
+use crate::encryption::Encryption;
+use sodiumoxide::crypto::secretstream::{gen_key, Header, Key, Stream, Tag, ABYTES, HEADERBYTES};
+use std::io::{Read, Write};
+
+/// Streamed files start with a line holding this prefix, followed by the key that the file
+/// is encrypted with, which is sealed with the public key from the configuration file. The
+/// rest of the file is a secretstream (XChaCha20-Poly1305) of chunks, the last of which is
+/// tagged as final so that truncated files are detected.
+pub const STREAM_PREFIX: &str = "scoob-stream:";
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Reads until the buffer is full, or the end of the reader is reached.
+fn read_chunk<R: Read>(reader: &mut R, buffer: &mut [u8]) -> Result<usize, &'static str> {
+    let mut length = 0;
+
+    while length < buffer.len() {
+        match reader.read(&mut buffer[length..]) {
+            Ok(0) => break,
+            Ok(read) => length += read,
+            Err(ref err) if err.kind() == std::io::ErrorKind::Interrupted => (),
+            Err(_) => return Err("Unable to read file"),
+        }
+    }
+
+    Ok(length)
+}
+
+fn write_all<W: Write>(writer: &mut W, contents: &[u8]) -> Result<(), &'static str> {
+    match writer.write_all(contents) {
+        Ok(_) => Ok(()),
+        Err(_) => Err("Unable to write file"),
+    }
+}
+
+pub fn encrypt_stream<R: Read, W: Write>(
+    encryption: &Encryption,
+    key: &str,
+    reader: &mut R,
+    writer: &mut W,
+) -> Result<(), &'static str> {
+    let stream_key = gen_key();
+    let sealed_key = encryption.encrypt_bytes(key, stream_key.as_ref())?;
+    write_all(
+        writer,
+        format!("{}{}\n", STREAM_PREFIX, sealed_key).as_bytes(),
+    )?;
+
+    let (mut stream, header) = match Stream::init_push(&stream_key) {
+        Ok(val) => val,
+        Err(_) => return Err("Failed to encrypt file."),
+    };
+    write_all(writer, header.as_ref())?;
+
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    loop {
+        let length = read_chunk(reader, &mut buffer)?;
+        let tag = if length < CHUNK_SIZE {
+            Tag::Final
+        } else {
+            Tag::Message
+        };
+
+        let chunk = match stream.push(&buffer[..length], None, tag) {
+            Ok(val) => val,
+            Err(_) => return Err("Failed to encrypt file."),
+        };
+        write_all(writer, &chunk)?;
+
+        if tag == Tag::Final {
+            return Ok(());
+        }
+    }
+}
+
+/// Decrypts a streamed file, where the first line of the file, holding the sealed key, has
+/// already been read from the reader.
+pub fn decrypt_stream<R: Read, W: Write>(
+    encryption: &Encryption,
+    key: &str,
+    first_line: &str,
+    reader: &mut R,
+    writer: &mut W,
+) -> Result<(), &'static str> {
+    let sealed_key = match first_line.trim().strip_prefix(STREAM_PREFIX) {
+        Some(val) => val,
+        None => return Err("The encrypted file is not a streamed file."),
+    };
+
+    let stream_key = match Key::from_slice(&encryption.decrypt_bytes(key, sealed_key)?) {
+        Some(val) => val,
+        None => return Err("Failed to decrypt file."),
+    };
+
+    let mut header = [0u8; HEADERBYTES];
+    if read_chunk(reader, &mut header)? < HEADERBYTES {
+        return Err("The encrypted file is truncated.");
+    }
+
+    let mut stream = match Stream::init_pull(&Header(header), &stream_key) {
+        Ok(val) => val,
+        Err(_) => return Err("Failed to decrypt file."),
+    };
+
+    let mut buffer = vec![0u8; CHUNK_SIZE + ABYTES];
+    loop {
+        let length = read_chunk(reader, &mut buffer)?;
+        if length < ABYTES {
+            return Err("The encrypted file is truncated.");
+        }
+
+        let (chunk, tag) = match stream.pull(&buffer[..length], None) {
+            Ok(val) => val,
+            Err(_) if length < buffer.len() => return Err("The encrypted file is truncated."),
+            Err(_) => return Err("Failed to decrypt file."),
+        };
+        write_all(writer, &chunk)?;
+
+        if tag == Tag::Final {
+            if read_chunk(reader, &mut buffer[..1])? > 0 {
+                return Err("The encrypted file has unexpected contents after its end.");
+            }
+            return Ok(());
+        }
+
+        if length < buffer.len() {
+            return Err("The encrypted file is truncated.");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use std::io::BufRead;
+
+    fn roundtrip(contents: &[u8]) -> Result<Vec<u8>, &'static str> {
+        let config = Config::default();
+        let encryption = Encryption { config: &config };
+
+        let mut encrypted: Vec<u8> = Vec::new();
+        encrypt_stream(&encryption, "*", &mut &contents[..], &mut encrypted)?;
+
+        let mut reader = &encrypted[..];
+        let mut first_line = String::new();
+        reader.read_line(&mut first_line).unwrap();
+
+        let mut decrypted: Vec<u8> = Vec::new();
+        decrypt_stream(&encryption, "*", &first_line, &mut reader, &mut decrypted)?;
+        Ok(decrypted)
+    }
+
+    #[test]
+    fn test_stream_roundtrip() -> Result<(), &'static str> {
+        for length in [0, 1, CHUNK_SIZE, CHUNK_SIZE * 2 + 7].iter() {
+            let contents: Vec<u8> = (0..*length).map(|i| (i % 251) as u8).collect();
+            assert_eq!(roundtrip(&contents)?, contents);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_stream_truncated() -> Result<(), &'static str> {
+        let config = Config::default();
+        let encryption = Encryption { config: &config };
+        let contents = vec![7u8; CHUNK_SIZE * 2];
+
+        let mut encrypted: Vec<u8> = Vec::new();
+        encrypt_stream(&encryption, "*", &mut &contents[..], &mut encrypted)?;
+
+        let mut reader = &encrypted[..];
+        let mut first_line = String::new();
+        reader.read_line(&mut first_line).unwrap();
+
+        // Drop the final chunk, which holds no contents:
+        let truncated = &reader[..reader.len() - ABYTES];
+        let result = decrypt_stream(
+            &encryption,
+            "*",
+            &first_line,
+            &mut &truncated[..],
+            &mut Vec::new(),
+        );
+        assert!(result.is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,496 @@
+use std::ffi::OsStr;
+#[cfg(unix)]
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::PathBuf;
+use std::process::{Child, Command, ExitStatus};
+#[cfg(unix)]
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::time::{Duration, Instant};
+
+#[cfg(unix)]
+static CHILD_PID: AtomicI32 = AtomicI32::new(0);
+#[cfg(unix)]
+static PENDING_SIGNAL: AtomicI32 = AtomicI32::new(0);
+
+/// The terminal sends SIGINT and SIGQUIT, for Ctrl-C and Ctrl-\, to every process in its
+/// foreground process group. A child that isn't in its own process group shares the group of
+/// scoob, so when that group is in the foreground the child already received the signal.
+#[cfg(unix)]
+fn is_forwarded(signal: libc::c_int, pid: i32, foreground_group: i32, group: i32) -> bool {
+    let from_terminal = signal == libc::SIGINT || signal == libc::SIGQUIT;
+    !(from_terminal && pid > 0 && foreground_group == group)
+}
+
+#[cfg(unix)]
+extern "C" fn forward_signal(signal: libc::c_int) {
+    let pid = CHILD_PID.load(Ordering::SeqCst);
+    if pid != 0 {
+        unsafe {
+            if is_forwarded(
+                signal,
+                pid,
+                libc::tcgetpgrp(libc::STDIN_FILENO),
+                libc::getpgrp(),
+            ) {
+                libc::kill(pid, signal);
+            }
+        }
+    } else {
+        PENDING_SIGNAL.store(signal, Ordering::SeqCst);
+    }
+}
+
+/// Forwards signals that scoob receives to the child, so that scoob outlives the child and is
+/// able to clean up after it. Signals received before the child started are forwarded once it
+/// has, unless the terminal already sent them to the child. The previous signal handlers are
+/// restored when dropped.
+#[cfg(unix)]
+struct SignalForwarder {
+    previous: Vec<(libc::c_int, libc::sigaction)>,
+}
+
+#[cfg(unix)]
+impl SignalForwarder {
+    fn new() -> SignalForwarder {
+        CHILD_PID.store(0, Ordering::SeqCst);
+        PENDING_SIGNAL.store(0, Ordering::SeqCst);
+        let mut previous = Vec::new();
+
+        for signal in [libc::SIGINT, libc::SIGTERM, libc::SIGHUP, libc::SIGQUIT].iter() {
+            unsafe {
+                let mut action: libc::sigaction = std::mem::zeroed();
+                action.sa_sigaction = forward_signal as extern "C" fn(libc::c_int) as usize;
+                libc::sigemptyset(&mut action.sa_mask);
+
+                let mut previous_action: libc::sigaction = std::mem::zeroed();
+                if libc::sigaction(*signal, &action, &mut previous_action) == 0 {
+                    previous.push((*signal, previous_action));
+                }
+            }
+        }
+
+        SignalForwarder { previous }
+    }
+
+    /// Forwards signals to the process, or to the process group when `pid` is negative.
+    fn forward_to(&self, pid: i32) {
+        CHILD_PID.store(pid, Ordering::SeqCst);
+
+        let pending = PENDING_SIGNAL.swap(0, Ordering::SeqCst);
+        if pending > 0 {
+            unsafe {
+                libc::kill(pid, pending);
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for SignalForwarder {
+    fn drop(&mut self) {
+        for (signal, action) in self.previous.iter() {
+            unsafe {
+                libc::sigaction(*signal, action, std::ptr::null_mut());
+            }
+        }
+        CHILD_PID.store(0, Ordering::SeqCst);
+    }
+}
+
+/// Ignores console control events, such as Ctrl-C, while the guard exists. The child shares
+/// the console of scoob, so it receives these events itself, and scoob has to outlive it to
+/// report its exit code.
+#[cfg(windows)]
+struct ConsoleGuard;
+
+#[cfg(windows)]
+type HandlerRoutine = unsafe extern "system" fn(u32) -> i32;
+
+#[cfg(windows)]
+#[link(name = "kernel32")]
+extern "system" {
+    fn SetConsoleCtrlHandler(handler: Option<HandlerRoutine>, add: i32) -> i32;
+}
+
+#[cfg(windows)]
+unsafe extern "system" fn ignore_console_event(_event: u32) -> i32 {
+    1
+}
+
+#[cfg(windows)]
+impl ConsoleGuard {
+    fn new() -> ConsoleGuard {
+        unsafe {
+            SetConsoleCtrlHandler(Some(ignore_console_event), 1);
+        }
+        ConsoleGuard
+    }
+}
+
+#[cfg(windows)]
+impl Drop for ConsoleGuard {
+    fn drop(&mut self) {
+        unsafe {
+            SetConsoleCtrlHandler(Some(ignore_console_event), 0);
+        }
+    }
+}
+
+/// Finds the program to run on Windows, where commands such as `npm` are often `.cmd` or
+/// `.bat` shims that are only found by trying the extensions in `PATHEXT`. Programs are
+/// returned as they are when `PATHEXT` is not set, which is the case on other platforms, or
+/// when they already have a path or one of the extensions.
+pub fn resolve_program(program: &str, path: Option<&OsStr>, pathext: Option<&OsStr>) -> PathBuf {
+    let (path, pathext) = match (path, pathext) {
+        (Some(path), Some(pathext)) => (path, pathext.to_string_lossy()),
+        _ => return PathBuf::from(program),
+    };
+
+    let extensions: Vec<&str> = pathext.split(';').filter(|e| !e.is_empty()).collect();
+    let has_extension = extensions
+        .iter()
+        .any(|extension| program.to_lowercase().ends_with(&extension.to_lowercase()));
+
+    if has_extension || program.contains('/') || program.contains('\\') {
+        return PathBuf::from(program);
+    }
+
+    for directory in std::env::split_paths(path) {
+        for extension in extensions.iter() {
+            // Extensions are matched case insensitively on Windows, but not on other platforms:
+            for extension in [extension.to_string(), extension.to_lowercase()].iter() {
+                let candidate = directory.join(format!("{}{}", program, extension));
+                if candidate.is_file() {
+                    return candidate;
+                }
+            }
+        }
+    }
+
+    PathBuf::from(program)
+}
+
+/// How long a command that is being stopped gets to exit, before it is killed.
+const STOP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Waits for the child to exit. When scoob runs as PID 1, such as in a Docker container, it
+/// also has to reap any orphaned processes that are reparented to it, which would otherwise
+/// be left behind as zombies.
+#[cfg(unix)]
+fn wait(child: &mut Child) -> std::io::Result<ExitStatus> {
+    if unsafe { libc::getpid() } != 1 {
+        return child.wait();
+    }
+
+    loop {
+        let mut status: libc::c_int = 0;
+        let pid = unsafe { libc::waitpid(-1, &mut status, 0) };
+
+        if pid == child.id() as i32 {
+            return Ok(ExitStatus::from_raw(status));
+        }
+
+        if pid == -1 {
+            let err = std::io::Error::last_os_error();
+            if err.kind() != std::io::ErrorKind::Interrupted {
+                return Err(err);
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn wait(child: &mut Child) -> std::io::Result<ExitStatus> {
+    child.wait()
+}
+
+/// Checks whether the child exited, without blocking. Orphaned processes are reaped the same
+/// way as in `wait`.
+#[cfg(unix)]
+fn try_wait(child: &mut Child) -> std::io::Result<Option<ExitStatus>> {
+    if unsafe { libc::getpid() } != 1 {
+        return child.try_wait();
+    }
+
+    loop {
+        let mut status: libc::c_int = 0;
+        let pid = unsafe { libc::waitpid(-1, &mut status, libc::WNOHANG) };
+
+        if pid == child.id() as i32 {
+            return Ok(Some(ExitStatus::from_raw(status)));
+        }
+
+        if pid == 0 {
+            return Ok(None);
+        }
+
+        if pid == -1 {
+            let err = std::io::Error::last_os_error();
+            if err.kind() != std::io::ErrorKind::Interrupted {
+                return Err(err);
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn try_wait(child: &mut Child) -> std::io::Result<Option<ExitStatus>> {
+    child.try_wait()
+}
+
+/// Commands that are killed by a signal exit with 128 plus the signal number, the same way
+/// that shells report them.
+fn exit_code(status: ExitStatus) -> i32 {
+    #[cfg(unix)]
+    {
+        if let Some(signal) = status.signal() {
+            return 128 + signal;
+        }
+    }
+
+    status.code().unwrap_or(1)
+}
+
+/// Runs commands as children of scoob, rather than replacing scoob with them. While the
+/// supervisor exists, SIGINT, SIGTERM, SIGHUP and SIGQUIT are forwarded to the latest child.
+pub struct Supervisor {
+    #[cfg(unix)]
+    forwarder: SignalForwarder,
+    #[cfg(unix)]
+    process_group: bool,
+    #[cfg(windows)]
+    _console: ConsoleGuard,
+}
+
+impl Supervisor {
+    pub fn new() -> Supervisor {
+        Supervisor {
+            #[cfg(unix)]
+            forwarder: SignalForwarder::new(),
+            #[cfg(unix)]
+            process_group: false,
+            #[cfg(windows)]
+            _console: ConsoleGuard::new(),
+        }
+    }
+
+    /// Runs each child in its own process group, and signals the whole group, so that the
+    /// processes that the child started are stopped along with it. The children aren't in the
+    /// foreground of the terminal, so this is only used for commands that get restarted.
+    pub fn with_process_group() -> Supervisor {
+        Supervisor {
+            #[cfg(unix)]
+            process_group: true,
+            ..Supervisor::new()
+        }
+    }
+
+    pub fn spawn(&self, command: &mut Command) -> Result<Child, &'static str> {
+        #[cfg(unix)]
+        if self.process_group {
+            command.process_group(0);
+        }
+
+        let child = match command.spawn() {
+            Ok(val) => val,
+            Err(_) => return Err("Failed to start command, please verify that it exists."),
+        };
+
+        #[cfg(unix)]
+        self.forwarder.forward_to(self.signal_target(&child));
+
+        Ok(child)
+    }
+
+    /// The process ID to send signals to, which is negated to signal the process group.
+    #[cfg(unix)]
+    fn signal_target(&self, child: &Child) -> i32 {
+        if self.process_group {
+            -(child.id() as i32)
+        } else {
+            child.id() as i32
+        }
+    }
+
+    /// Waits for the child to exit, and returns its exit code.
+    pub fn wait(&self, child: &mut Child) -> Result<i32, &'static str> {
+        match wait(child) {
+            Ok(status) => Ok(exit_code(status)),
+            Err(_) => Err("Failed to wait for the command to exit."),
+        }
+    }
+
+    /// Returns the exit code of the child if it exited, without blocking.
+    pub fn try_wait(&self, child: &mut Child) -> Result<Option<i32>, &'static str> {
+        match try_wait(child) {
+            Ok(status) => Ok(status.map(exit_code)),
+            Err(_) => Err("Failed to wait for the command to exit."),
+        }
+    }
+
+    #[cfg(unix)]
+    pub fn signal(&self, child: &Child, signal: i32) {
+        unsafe {
+            libc::kill(self.signal_target(child), signal);
+        }
+    }
+
+    /// Asks the child to exit with SIGTERM, and kills it when it doesn't exit in time.
+    pub fn stop(&self, child: &mut Child) -> Result<i32, &'static str> {
+        #[cfg(unix)]
+        self.signal(child, libc::SIGTERM);
+        #[cfg(not(unix))]
+        child.kill().ok();
+
+        let deadline = Instant::now() + STOP_TIMEOUT;
+        while Instant::now() < deadline {
+            if let Some(code) = self.try_wait(child)? {
+                return Ok(code);
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+
+        #[cfg(unix)]
+        self.signal(child, libc::SIGKILL);
+        child.kill().ok();
+        self.wait(child)
+    }
+}
+
+/// Parses a signal name such as `HUP` or `SIGHUP`, or a signal number.
+#[cfg(unix)]
+pub fn parse_signal(name: &str) -> Result<i32, String> {
+    if let Ok(number) = name.parse::<i32>() {
+        return Ok(number);
+    }
+
+    let signal = match name.to_uppercase().trim_start_matches("SIG") {
+        "HUP" => libc::SIGHUP,
+        "INT" => libc::SIGINT,
+        "QUIT" => libc::SIGQUIT,
+        "TERM" => libc::SIGTERM,
+        "USR1" => libc::SIGUSR1,
+        "USR2" => libc::SIGUSR2,
+        "WINCH" => libc::SIGWINCH,
+        _ => return Err(format!("Unknown signal: {}", name)),
+    };
+
+    Ok(signal)
+}
+
+#[cfg(not(unix))]
+pub fn parse_signal(_name: &str) -> Result<i32, String> {
+    Err("Signals are only supported on Unix.".to_string())
+}
+
+/// Runs the command as a child of scoob, and returns its exit code once it exits.
+pub fn supervise(command: &mut Command) -> Result<i32, &'static str> {
+    let supervisor = Supervisor::new();
+    let mut child = supervisor.spawn(command)?;
+    supervisor.wait(&mut child)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_supervise() -> Result<(), &'static str> {
+        assert_eq!(supervise(Command::new("sh").args(["-c", "exit 3"]))?, 3);
+        assert!(supervise(&mut Command::new("command_does_not_exist")).is_err());
+
+        #[cfg(unix)]
+        assert_eq!(
+            supervise(Command::new("sh").args(["-c", "kill -TERM $$"]))?,
+            128 + libc::SIGTERM
+        );
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_parse_signal() {
+        assert_eq!(parse_signal("HUP"), Ok(libc::SIGHUP));
+        assert_eq!(parse_signal("sigusr1"), Ok(libc::SIGUSR1));
+        assert_eq!(parse_signal("15"), Ok(15));
+        assert!(parse_signal("RELOAD").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_is_forwarded() {
+        // The child shares the foreground group, so the terminal already signalled it:
+        assert!(!is_forwarded(libc::SIGINT, 100, 50, 50));
+        assert!(!is_forwarded(libc::SIGQUIT, 100, 50, 50));
+        assert!(is_forwarded(libc::SIGTERM, 100, 50, 50));
+
+        // Scoob isn't in the foreground, or the child runs in its own process group:
+        assert!(is_forwarded(libc::SIGINT, 100, 40, 50));
+        assert!(is_forwarded(libc::SIGINT, 100, -1, 50));
+        assert!(is_forwarded(libc::SIGINT, -100, 50, 50));
+    }
+
+    #[test]
+    fn test_resolve_program() {
+        let directory = tempfile::tempdir().unwrap();
+        let shim = directory.path().join("npm.cmd");
+        std::fs::write(&shim, "@echo off").unwrap();
+
+        let path = std::env::join_paths(vec![
+            PathBuf::from("/does-not-exist"),
+            directory.path().to_path_buf(),
+        ])
+        .unwrap();
+        let pathext = OsStr::new(".COM;.EXE;.BAT;.CMD");
+
+        assert_eq!(resolve_program("npm", Some(&path), Some(pathext)), shim);
+        assert_eq!(
+            resolve_program("npm.cmd", Some(&path), Some(pathext)),
+            PathBuf::from("npm.cmd")
+        );
+        assert_eq!(
+            resolve_program("node", Some(&path), Some(pathext)),
+            PathBuf::from("node")
+        );
+        assert_eq!(
+            resolve_program("npm", Some(&path), None),
+            PathBuf::from("npm")
+        );
+    }
+
+    #[test]
+    fn test_supervisor_stop() -> Result<(), &'static str> {
+        let supervisor = Supervisor::new();
+        let mut child = supervisor.spawn(Command::new("sleep").arg("10"))?;
+        assert_eq!(supervisor.try_wait(&mut child)?, None);
+
+        let code = supervisor.stop(&mut child)?;
+        #[cfg(unix)]
+        assert_eq!(code, 128 + libc::SIGTERM);
+        #[cfg(not(unix))]
+        assert_ne!(code, 0);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_supervisor_stop_process_group() -> Result<(), &'static str> {
+        let directory = tempfile::tempdir().unwrap();
+        let survived_file = directory.path().join("survived");
+
+        // The background process only creates the file when it outlives the shell:
+        let supervisor = Supervisor::with_process_group();
+        let mut child = supervisor.spawn(Command::new("sh").args([
+            "-c",
+            "(sleep 1; touch \"$1\") & wait",
+            "sh",
+            &survived_file.to_string_lossy(),
+        ]))?;
+        std::thread::sleep(Duration::from_millis(100));
+
+        assert_eq!(supervisor.stop(&mut child)?, 128 + libc::SIGTERM);
+        std::thread::sleep(Duration::from_millis(1500));
+        assert!(!survived_file.exists());
+        Ok(())
+    }
+}
@@ -0,0 +1,194 @@
+#[cfg(unix)]
+use std::fs::Permissions;
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use tempfile::NamedTempFile;
+
+/// Permissions that the temporary file is created with, which a new file keeps. Private files
+/// are only readable by the owner. Other files are created with the same mode as
+/// `std::fs::write` uses, so the umask applies to them in the same way.
+#[cfg(unix)]
+fn new_file_permissions(private: bool) -> Permissions {
+    Permissions::from_mode(if private { 0o600 } else { 0o666 })
+}
+
+/// A file that is written to a temporary file next to the destination, which is renamed into
+/// place when it is committed, so that a crash never leaves a partially written file behind.
+/// When the destination already exists, its permissions and ownership are kept, except for
+/// private files, which are always only readable by the owner. A destination that is a
+/// symlink is followed, so the file it points to is replaced rather than the link. Dropping
+/// the file without committing it removes the temporary file.
+pub struct AtomicFile {
+    path: PathBuf,
+    temp_file: NamedTempFile,
+    private: bool,
+}
+
+/// Follows a symlink to the file that it points to. Other paths, and links that can't be
+/// resolved, are returned as they are.
+fn resolve_symlink(path: &Path) -> PathBuf {
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_symlink() => {
+            std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+        }
+        _ => path.to_path_buf(),
+    }
+}
+
+impl AtomicFile {
+    pub fn create(path: &Path, private: bool) -> std::io::Result<AtomicFile> {
+        let path = resolve_symlink(path);
+        let mut builder = tempfile::Builder::new();
+        builder.prefix(".scoob");
+        #[cfg(unix)]
+        builder.permissions(new_file_permissions(private));
+
+        Ok(AtomicFile {
+            temp_file: builder.tempfile_in(parent_directory(&path))?,
+            path,
+            private,
+        })
+    }
+
+    pub fn commit(self) -> std::io::Result<()> {
+        let temp_file = self.temp_file;
+
+        // Private files keep the permissions they were created with, so that decrypting over
+        // a readable file doesn't leave the decrypted file readable by others:
+        let existing = if self.private {
+            None
+        } else {
+            std::fs::metadata(&self.path).ok()
+        };
+        if let Some(metadata) = existing {
+            std::fs::set_permissions(temp_file.path(), metadata.permissions())?;
+
+            // Ownership can only be changed by privileged users, so this is allowed to fail:
+            #[cfg(unix)]
+            unsafe {
+                use std::os::unix::io::AsRawFd;
+                libc::fchown(
+                    temp_file.as_file().as_raw_fd(),
+                    metadata.uid(),
+                    metadata.gid(),
+                );
+            }
+        }
+
+        temp_file.as_file().sync_all()?;
+        temp_file.persist(&self.path).map_err(|err| err.error)?;
+
+        // Sync the directory, so that the rename itself is durable:
+        #[cfg(unix)]
+        std::fs::File::open(parent_directory(&self.path))?.sync_all()?;
+
+        Ok(())
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.temp_file.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.temp_file.flush()
+    }
+}
+
+fn parent_directory(path: &Path) -> &Path {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    }
+}
+
+/// Atomically writes a file, such as a scoob configuration file or an encrypted file.
+pub fn write(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut file = AtomicFile::create(path, false)?;
+    file.write_all(contents)?;
+    file.commit()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write() -> std::io::Result<()> {
+        let directory = tempfile::tempdir()?;
+        let path = directory.path().join("secrets.yml");
+
+        write(&path, b"first")?;
+        assert_eq!(std::fs::read_to_string(&path)?, "first");
+        write(&path, b"second")?;
+        assert_eq!(std::fs::read_to_string(&path)?, "second");
+
+        // Only the written file is left in the directory:
+        assert_eq!(std::fs::read_dir(directory.path())?.count(), 1);
+
+        // Files that aren't committed are discarded:
+        let mut file = AtomicFile::create(&path, false)?;
+        file.write_all(b"third")?;
+        drop(file);
+        assert_eq!(std::fs::read_to_string(&path)?, "second");
+        assert_eq!(std::fs::read_dir(directory.path())?.count(), 1);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_permissions() -> std::io::Result<()> {
+        let directory = tempfile::tempdir()?;
+        let private_path = directory.path().join("decrypted");
+        let path = directory.path().join("secrets.yml");
+
+        let mut private_file = AtomicFile::create(&private_path, true)?;
+        private_file.write_all(b"decrypted")?;
+        private_file.commit()?;
+        let mode = std::fs::metadata(&private_path)?.permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        // New files get the same permissions as files created by std::fs::write:
+        let plain_path = directory.path().join("plain");
+        std::fs::write(&plain_path, b"plain")?;
+        write(&path, b"first")?;
+        assert_eq!(
+            std::fs::metadata(&path)?.permissions().mode(),
+            std::fs::metadata(&plain_path)?.permissions().mode()
+        );
+
+        write(&path, b"first")?;
+        std::fs::set_permissions(&path, Permissions::from_mode(0o640))?;
+        write(&path, b"second")?;
+        let mode = std::fs::metadata(&path)?.permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+
+        // Private files never keep the permissions of the file they replace:
+        std::fs::set_permissions(&private_path, Permissions::from_mode(0o644))?;
+        let mut private_file = AtomicFile::create(&private_path, true)?;
+        private_file.write_all(b"decrypted again")?;
+        private_file.commit()?;
+        let mode = std::fs::metadata(&private_path)?.permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_symlink() -> std::io::Result<()> {
+        let directory = tempfile::tempdir()?;
+        let path = directory.path().join("secrets.yml");
+        let link = directory.path().join("link.yml");
+        std::fs::write(&path, b"first")?;
+        std::os::unix::fs::symlink(&path, &link)?;
+
+        // Writing through a symlink replaces the file it points to, and keeps the link:
+        write(&link, b"second")?;
+        assert!(std::fs::symlink_metadata(&link)?.file_type().is_symlink());
+        assert_eq!(std::fs::read_to_string(&path)?, "second");
+        Ok(())
+    }
+}